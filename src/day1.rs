@@ -1,10 +1,17 @@
-use crate::utils::HighestNIter;
+use crate::{utils::HighestNIter, ParseError};
 
 #[aoc_generator(day1)]
-fn gerator(input: &str) -> Vec<u32> {
+fn gerator(input: &str) -> Result<Vec<u32>, ParseError> {
     input
         .split("\n\n")
-        .map(|s| s.lines().flat_map(str::parse::<u32>).sum())
+        .map(|s| {
+            s.lines()
+                .map(|line| {
+                    line.parse::<u32>()
+                        .map_err(|_| ParseError::at(1, input, line, "a calorie count"))
+                })
+                .sum()
+        })
         .collect()
 }
 
@@ -1,8 +1,11 @@
-use itertools::{repeat_n, Itertools};
-use std::str::FromStr;
+use crate::ParseError;
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Write},
+};
 
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
-enum Opcode {
+pub enum Opcode {
     #[default]
     Noop,
     AddX,
@@ -10,95 +13,464 @@ enum Opcode {
 
 type Cycles = u8;
 
-struct Instruction {
-    _opcode: Opcode,
-    cycles: Cycles,
-    arg: Option<i64>,
+#[derive(Debug)]
+pub struct OpcodeSpec {
+    pub opcode: Opcode,
+    pub mnemonic: &'static str,
+    pub cycles: Cycles,
+    pub takes_arg: bool,
 }
 
-impl Default for Instruction {
-    fn default() -> Self {
-        Self {
-            _opcode: Opcode::Noop,
-            cycles: 1,
-            arg: None,
+/// Every instruction the CPU understands. Supporting a new one takes a row
+/// here, an `Opcode` variant and its effect in `Opcode::execute`.
+pub const OPCODES: [OpcodeSpec; 2] = [
+    OpcodeSpec {
+        opcode: Opcode::Noop,
+        mnemonic: "noop",
+        cycles: 1,
+        takes_arg: false,
+    },
+    OpcodeSpec {
+        opcode: Opcode::AddX,
+        mnemonic: "addx",
+        cycles: 2,
+        takes_arg: true,
+    },
+];
+
+impl Opcode {
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        OPCODES
+            .iter()
+            .find(|spec| spec.mnemonic == mnemonic)
+            .map(|spec| spec.opcode)
+    }
+    pub fn spec(self) -> &'static OpcodeSpec {
+        OPCODES
+            .iter()
+            .find(|spec| spec.opcode == self)
+            .expect("every opcode has a spec")
+    }
+    /// Applies the instruction's effect, once its last cycle is over.
+    fn execute(self, registers: &mut Registers, arg: Option<i64>) {
+        match self {
+            Self::Noop => (),
+            Self::AddX => registers.x += arg.unwrap_or_default(),
         }
     }
 }
 
-impl FromStr for Instruction {
-    type Err = ();
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(s.split_once(' ')
-            .map_or_else(Instruction::default, |(_, arg)| {
-                Instruction::from_opcode(Opcode::AddX, arg.parse::<i64>().ok())
-            }))
-    }
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct Instruction {
+    opcode: Opcode,
+    arg: Option<i64>,
 }
 
 impl Instruction {
-    pub fn from_opcode(opcode: Opcode, arg: Option<i64>) -> Self {
-        let cycles = match opcode {
-            Opcode::Noop => 1,
-            Opcode::AddX => 2,
-        };
+    pub fn new(opcode: Opcode, arg: Option<i64>) -> Self {
+        Self { opcode, arg }
+    }
+    pub fn cycles(&self) -> Cycles {
+        self.opcode.spec().cycles
+    }
+}
+
+/// Parses a mnemonic from `OPCODES`, followed by its argument if it takes one.
+fn parse_instruction(input: &str, line: &str) -> Result<Instruction, ParseError> {
+    let (mnemonic, arg) = line
+        .split_once(' ')
+        .map_or((line, None), |(mnemonic, arg)| (mnemonic, Some(arg)));
+    let opcode = Opcode::from_mnemonic(mnemonic).ok_or_else(|| {
+        let mnemonics = OPCODES
+            .iter()
+            .map(|spec| format!("`{}`", spec.mnemonic))
+            .collect::<Vec<_>>();
+        ParseError::at(10, input, line, format!("one of {}", mnemonics.join(", ")))
+    })?;
+    let arg = match (opcode.spec().takes_arg, arg) {
+        (true, Some(arg)) => Some(
+            arg.parse::<i64>()
+                .map_err(|_| ParseError::at(10, input, arg, "an integer argument"))?,
+        ),
+        (true, None) => {
+            return Err(ParseError::at_byte(
+                10,
+                input,
+                line,
+                line.len(),
+                "an integer argument",
+            ))
+        }
+        (false, Some(arg)) => return Err(ParseError::at(10, input, arg, "the end of the line")),
+        (false, None) => None,
+    };
+
+    Ok(Instruction::new(opcode, arg))
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Registers {
+    pub x: i64,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self { x: 1 }
+    }
+}
+
+/// The registers during a cycle, before the instruction being run updates them.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CycleState {
+    /// Starting from 1.
+    pub cycle: u64,
+    pub registers: Registers,
+}
+
+type Hook<'h> = (BTreeSet<u64>, Box<dyn FnMut(&CycleState) + 'h>);
+
+/// Runs a program borrowed for `'p`, calling hooks that may borrow for `'h`.
+pub struct Cpu<'p, 'h> {
+    program: &'p [Instruction],
+    pc: usize,
+    registers: Registers,
+    cycle: u64,
+    /// Cycles already spent on the instruction at `pc`.
+    elapsed: Cycles,
+    breakpoints: BTreeSet<u64>,
+    hooks: Vec<Hook<'h>>,
+}
+
+impl<'p, 'h> Cpu<'p, 'h> {
+    pub fn new(program: &'p [Instruction]) -> Self {
         Self {
-            cycles,
-            _opcode: opcode,
-            arg,
+            program,
+            pc: 0,
+            registers: Registers::default(),
+            cycle: 0,
+            elapsed: 0,
+            breakpoints: BTreeSet::new(),
+            hooks: vec![],
+        }
+    }
+    pub fn registers(&self) -> Registers {
+        self.registers
+    }
+    /// The number of cycles run so far.
+    pub fn clock(&self) -> u64 {
+        self.cycle
+    }
+    pub fn is_halted(&self) -> bool {
+        self.pc >= self.program.len()
+    }
+    /// Makes `run` stop after `cycle`.
+    pub fn add_breakpoint(&mut self, cycle: u64) {
+        self.breakpoints.insert(cycle);
+    }
+    /// Calls `hook` with the state of each of `cycles`, as they're run.
+    pub fn add_hook<I, F>(&mut self, cycles: I, hook: F)
+    where
+        I: IntoIterator<Item = u64>,
+        F: FnMut(&CycleState) + 'h,
+    {
+        self.hooks
+            .push((cycles.into_iter().collect(), Box::new(hook)));
+    }
+    /// Runs a single cycle, returning the registers during it,
+    /// or `None` if the program is over.
+    pub fn step(&mut self) -> Option<CycleState> {
+        let instruction = self.program.get(self.pc)?;
+        self.cycle += 1;
+        let state = CycleState {
+            cycle: self.cycle,
+            registers: self.registers,
+        };
+        for (_, hook) in self
+            .hooks
+            .iter_mut()
+            .filter(|(cycles, _)| cycles.contains(&state.cycle))
+        {
+            hook(&state);
+        }
+
+        self.elapsed += 1;
+        if self.elapsed >= instruction.cycles() {
+            instruction
+                .opcode
+                .execute(&mut self.registers, instruction.arg);
+            self.pc += 1;
+            self.elapsed = 0;
         }
+
+        Some(state)
+    }
+    /// Runs until a breakpoint, returning the state of its cycle,
+    /// or until the program is over, returning `None`.
+    pub fn run(&mut self) -> Option<CycleState> {
+        while let Some(state) = self.step() {
+            if self.breakpoints.contains(&state.cycle) {
+                return Some(state);
+            }
+        }
+        None
+    }
+}
+
+impl Iterator for Cpu<'_, '_> {
+    type Item = CycleState;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step()
     }
 }
 
 #[aoc_generator(day10)]
-fn generator(input: &str) -> Vec<Instruction> {
-    input.lines().flat_map(Instruction::from_str).collect()
+fn generator(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    input
+        .lines()
+        .map(|line| parse_instruction(input, line))
+        .collect()
 }
 
 #[aoc(day10, part1)]
 fn part1(input: &[Instruction]) -> i64 {
-    input
-        .iter()
-        .scan(1, |x, instruction| {
-            let x_before_update = *x;
-            if let Some(arg) = instruction.arg {
-                *x += arg;
+    let mut signal_strength = 0;
+    {
+        let mut cpu = Cpu::new(input);
+        cpu.add_hook([20, 60, 100, 140, 180, 220], |state| {
+            signal_strength += state.cycle as i64 * state.registers.x
+        });
+        cpu.run();
+    }
+
+    signal_strength
+}
+
+pub const CRT_WIDTH: usize = 40;
+pub const CRT_HEIGHT: usize = 6;
+
+const GLYPH_WIDTH: usize = 4;
+
+/// The capital letters AoC draws on the CRT, each 4 pixels wide and 6 tall.
+/// `Y` is 5 pixels wide, so it doesn't fit the 5 columns pitch and is left out.
+const GLYPHS: [(char, [&str; CRT_HEIGHT]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+/// What the CRT shows once the CPU has run for its 240 cycles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrtScreen {
+    pixels: [[bool; CRT_WIDTH]; CRT_HEIGHT],
+}
+
+impl CrtScreen {
+    /// Draws a pixel per cycle, lit whenever the 3 pixels wide sprite centered
+    /// on the X register covers the position being drawn.
+    pub fn draw<I: IntoIterator<Item = i64>>(sprite_positions: I) -> Self {
+        let mut pixels = [[false; CRT_WIDTH]; CRT_HEIGHT];
+        for (clock, sprite_pos) in (0..CRT_WIDTH * CRT_HEIGHT).zip(sprite_positions) {
+            let (row, col) = (clock / CRT_WIDTH, clock % CRT_WIDTH);
+            pixels[row][col] = sprite_pos.abs_diff(col as i64) <= 1;
+        }
+        Self { pixels }
+    }
+    pub fn is_lit(&self, row: usize, col: usize) -> bool {
+        self.pixels[row][col]
+    }
+    /// Reads the letters on the screen, or `None` if some of them aren't recognised.
+    pub fn decode(&self) -> Option<String> {
+        (0..CRT_WIDTH / (GLYPH_WIDTH + 1))
+            .map(|i| self.decode_glyph(i * (GLYPH_WIDTH + 1)))
+            .collect()
+    }
+    fn decode_glyph(&self, first_col: usize) -> Option<char> {
+        GLYPHS
+            .iter()
+            .find(|(_, glyph)| {
+                glyph
+                    .iter()
+                    .zip(self.pixels.iter())
+                    .all(|(glyph_row, row)| {
+                        glyph_row
+                            .bytes()
+                            .zip(&row[first_col..first_col + GLYPH_WIDTH])
+                            .all(|(glyph_pixel, lit)| (glyph_pixel == b'#') == *lit)
+                    })
+            })
+            .map(|(letter, _)| *letter)
+    }
+}
+
+impl fmt::Display for CrtScreen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.pixels.iter() {
+            for lit in row {
+                f.write_char(if *lit { '#' } else { '.' })?;
             }
-            Some(repeat_n(x_before_update, instruction.cycles as usize))
-        })
-        .flatten()
-        .zip(1_u64..)
-        .filter_map(|(x, clock)| {
-            [20, 60, 100, 140, 180, 220]
-                .contains(&clock)
-                .then_some(x * clock as i64)
-        })
-        .sum()
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
 }
 
 #[aoc(day10, part2)]
-fn part2(input: &[Instruction]) -> Option<i32> {
-    input
-        .iter()
-        .scan(1, |x, instruction| {
-            let x_before_update = *x;
-            if let Some(arg) = instruction.arg {
-                *x += arg;
-            }
-            Some(repeat_n(x_before_update, instruction.cycles as usize))
-        })
-        .flatten()
-        .zip(0_i64..)
-        .map(|(center_pos, clock)| {
-            let scan_line = clock % 40;
-            if [center_pos - 1, center_pos, center_pos + 1].contains(&scan_line) {'#'}  else {'.'}
-        })
-        .chunks(40)
-        .into_iter()
-        .for_each(|chunk| {
-            chunk.for_each(|pixel| print!("{}", pixel));
-            println!();
-        });
+fn part2(input: &[Instruction]) -> Option<String> {
+    let sprite_positions = Cpu::new(input).map(|state| state.registers.x);
+
+    CrtScreen::draw(sprite_positions).decode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LARGE_EXAMPLE: &str = include_str!("../input/2022/examples/day10.txt");
+
+    /// The sprite positions drawing a screen like `CrtScreen`'s `Display`.
+    fn sprite_positions(screen: &str) -> Vec<i64> {
+        screen
+            .lines()
+            .flat_map(|row| row.bytes().enumerate())
+            // Unlit pixels get a sprite far enough not to cover them.
+            .map(|(col, pixel)| col as i64 + if pixel == b'#' { 0 } else { 5 })
+            .collect()
+    }
+
+    const SMALL_EXAMPLE: &str = "noop\naddx 3\naddx -5";
+
+    #[test]
+    fn breakpoints() {
+        let program = generator(SMALL_EXAMPLE).unwrap();
+        let mut cpu = Cpu::new(&program);
+        cpu.add_breakpoint(2);
+        cpu.add_breakpoint(4);
+        cpu.add_breakpoint(9);
+        let at = |cycle, x| CycleState {
+            cycle,
+            registers: Registers { x },
+        };
+        assert_eq!(cpu.run(), Some(at(2, 1)));
+        assert_eq!(cpu.run(), Some(at(4, 4)));
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.run(), None);
+        assert!(cpu.is_halted());
+        assert_eq!((cpu.clock(), cpu.registers().x), (5, -1));
+        assert_eq!(cpu.run(), None);
+        assert_eq!(cpu.clock(), 5);
+    }
 
-    None
-}
\ No newline at end of file
+    #[test]
+    fn hooks() {
+        let program = generator(SMALL_EXAMPLE).unwrap();
+        // The hooks borrow state that doesn't live as long as the program.
+        let mut odd = vec![];
+        let mut late = vec![];
+        let mut cpu = Cpu::new(&program);
+        cpu.add_hook([1, 3, 5], |state| odd.push(state.registers.x));
+        cpu.add_hook([3, 4, 99], |state| late.push(state.cycle));
+        cpu.add_breakpoint(3);
+        assert_eq!(cpu.run().map(|state| state.cycle), Some(3));
+        cpu.run();
+        drop(cpu);
+        assert_eq!(odd, [1, 1, 4]);
+        assert_eq!(late, [3, 4]);
+    }
+
+    #[test]
+    fn unknown_mnemonic() {
+        let err = generator("noop\nmulx 3").unwrap_err();
+        assert_eq!((err.line(), err.column()), (2, 1));
+        assert_eq!(err.expected(), "one of `noop`, `addx`");
+        let err = generator("noop 1").unwrap_err();
+        assert_eq!((err.column(), err.expected()), (6, "the end of the line"));
+        let err = generator("addx").unwrap_err();
+        assert_eq!((err.column(), err.expected()), (5, "an integer argument"));
+    }
+
+    #[test]
+    fn large_example() {
+        let program = generator(LARGE_EXAMPLE.trim_end()).unwrap();
+        assert_eq!(part1(&program), 13140);
+        let screen = CrtScreen::draw(Cpu::new(&program).map(|state| state.registers.x));
+        assert_eq!(
+            screen.to_string(),
+            "\
+##..##..##..##..##..##..##..##..##..##..
+###...###...###...###...###...###...###.
+####....####....####....####....####....
+#####.....#####.....#####.....#####.....
+######......######......######......####
+#######.......#######.......#######.....
+"
+        );
+        assert_eq!(screen.decode(), None);
+    }
+
+    #[test]
+    fn puzzle_input() {
+        let program = generator(include_str!("../input/2022/day10.txt").trim_end()).unwrap();
+        assert_eq!(part2(&program).as_deref(), Some("RGLRBZAU"));
+    }
+
+    #[test]
+    fn decode_glyphs() {
+        let screens = [
+            (
+                "ABCEFGHI",
+                "\
+.##..###...##..####.####..##..#..#..###.
+#..#.#..#.#..#.#....#....#..#.#..#...#..
+#..#.###..#....###..###..#....####...#..
+####.#..#.#....#....#....#.##.#..#...#..
+#..#.#..#.#..#.#....#....#..#.#..#...#..
+#..#.###...##..####.#.....###.#..#..###.
+",
+            ),
+            (
+                "JKLOPRSU",
+                "\
+..##.#..#.#.....##..###..###...###.#..#.
+...#.#.#..#....#..#.#..#.#..#.#....#..#.
+...#.##...#....#..#.#..#.#..#.#....#..#.
+...#.#.#..#....#..#.###..###...##..#..#.
+#..#.#.#..#....#..#.#....#.#.....#.#..#.
+.##..#..#.####..##..#....#..#.###...##..
+",
+            ),
+            (
+                "ZALGEBRA",
+                "\
+####..##..#.....##..####.###..###...##..
+...#.#..#.#....#..#.#....#..#.#..#.#..#.
+..#..#..#.#....#....###..###..#..#.#..#.
+.#...####.#....#.##.#....#..#.###..####.
+#....#..#.#....#..#.#....#..#.#.#..#..#.
+####.#..#.####..###.####.###..#..#.#..#.
+",
+            ),
+        ];
+        for (letters, pixels) in screens {
+            let screen = CrtScreen::draw(sprite_positions(pixels));
+            assert_eq!(screen.to_string(), pixels);
+            assert_eq!(screen.decode().as_deref(), Some(letters));
+        }
+        // A glyph with a pixel off isn't recognised.
+        let typo = screens[0].1.replacen(".##.", ".#..", 1);
+        assert_eq!(CrtScreen::draw(sprite_positions(&typo)).decode(), None);
+    }
+}
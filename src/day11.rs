@@ -1,25 +1,106 @@
-use crate::utils::HighestNIter;
+use crate::{utils::HighestNIter, ParseError};
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{char, multispace1, u64 as parse_u64},
-    combinator::map,
-    multi::separated_list1,
-    sequence::{delimited, preceded, tuple},
+    character::complete::{char, line_ending, multispace1, one_of, space0, u64 as parse_u64},
+    combinator::{eof, map, value, verify},
+    multi::{fold_many0, separated_list1},
+    sequence::{delimited, pair, preceded, tuple},
     IResult,
 };
-use std::collections::VecDeque;
+use std::{collections::VecDeque, error::Error, fmt};
 
-#[derive(Debug, Default)]
-enum Operation {
-    Multiply(u64),
+/// The right hand side of a monkey's `new = ...` operation.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Operation {
     #[default]
-    Square,
-    Sum(u64),
+    Old,
+    Const(u64),
+    Add(Box<Operation>, Box<Operation>),
+    Sub(Box<Operation>, Box<Operation>),
+    Mul(Box<Operation>, Box<Operation>),
 }
 
-#[derive(Debug)]
-struct Monkey {
+impl Operation {
+    /// Computes the new worry level, or `None` if it doesn't fit in a `u64`.
+    /// Under a modulus every step is reduced, so the result is always defined
+    /// and a subtraction going below zero wraps around the modulus.
+    pub fn eval(&self, old: u64, modulus: Option<u64>) -> Option<u64> {
+        match modulus {
+            Some(modulus) => self.eval_mod(old, modulus),
+            None => self.eval_checked(old),
+        }
+    }
+    fn eval_checked(&self, old: u64) -> Option<u64> {
+        match self {
+            Self::Old => Some(old),
+            Self::Const(x) => Some(*x),
+            Self::Add(lhs, rhs) => lhs.eval_checked(old)?.checked_add(rhs.eval_checked(old)?),
+            Self::Sub(lhs, rhs) => lhs.eval_checked(old)?.checked_sub(rhs.eval_checked(old)?),
+            Self::Mul(lhs, rhs) => lhs.eval_checked(old)?.checked_mul(rhs.eval_checked(old)?),
+        }
+    }
+    fn eval_mod(&self, old: u64, modulus: u64) -> Option<u64> {
+        let m = modulus as u128;
+        let result = match self {
+            Self::Old => old.checked_rem(modulus)? as u128,
+            Self::Const(x) => x.checked_rem(modulus)? as u128,
+            Self::Add(lhs, rhs) => {
+                (lhs.eval_mod(old, modulus)? as u128 + rhs.eval_mod(old, modulus)? as u128) % m
+            }
+            Self::Sub(lhs, rhs) => {
+                (lhs.eval_mod(old, modulus)? as u128 + m - rhs.eval_mod(old, modulus)? as u128) % m
+            }
+            Self::Mul(lhs, rhs) => {
+                (lhs.eval_mod(old, modulus)? as u128 * rhs.eval_mod(old, modulus)? as u128) % m
+            }
+        };
+        Some(result as u64)
+    }
+}
+
+/// An item whose worry level overflowed while a monkey was inspecting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorryOverflow {
+    pub monkey: usize,
+    pub level: u64,
+}
+
+impl fmt::Display for WorryOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "monkey {} overflowed the worry level of an item at {}",
+            self.monkey, self.level
+        )
+    }
+}
+
+impl Error for WorryOverflow {}
+
+/// A setting `MonkeySim` can't play with, since it would divide by zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidSetup {
+    ZeroReliefDivisor,
+    ZeroModulus,
+    /// The least common multiple of the test divisors doesn't fit in a `u64`.
+    ModulusOverflow,
+}
+
+impl fmt::Display for InvalidSetup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroReliefDivisor => write!(f, "the relief can't divide worry levels by 0"),
+            Self::ZeroModulus => write!(f, "worry levels can't be kept modulo 0"),
+            Self::ModulusOverflow => write!(f, "the test divisors' common multiple overflows"),
+        }
+    }
+}
+
+impl Error for InvalidSetup {}
+
+#[derive(Debug, Clone)]
+pub struct Monkey {
     starting_items: VecDeque<u64>,
     operation: Operation,
     test_divisor: u64,
@@ -31,7 +112,7 @@ impl Default for Monkey {
     fn default() -> Self {
         Self {
             starting_items: VecDeque::new(),
-            operation: Operation::Square,
+            operation: Operation::Old,
             test_divisor: 0,
             test_branch: (0, 0),
             thrown_items_counter: 0,
@@ -40,50 +121,229 @@ impl Default for Monkey {
 }
 
 impl Monkey {
-    pub fn throw(&mut self, others: &mut [Monkey], worry_level_decreases: bool, test_divider: u64) {
-        let divider = if worry_level_decreases { 3 } else { 1 };
-        self.starting_items.drain(..).map(|level| match self.operation {
-            Operation::Multiply(x) => level * x,
-            Operation::Square => level * level,
-            Operation::Sum(x) => level + x,
-        } / divider)
-        .for_each(|mut level| {
-            level %= test_divider;
-            if level % self.test_divisor == 0 {
-                others[self.test_branch.0].catch(level);
+    pub fn items(&self) -> impl Iterator<Item = u64> + '_ {
+        self.starting_items.iter().copied()
+    }
+    pub fn thrown_items_counter(&self) -> usize {
+        self.thrown_items_counter
+    }
+    /// Throws every item, returning the worry level of the first one that overflowed.
+    /// That item stays with the monkey, along with the ones not yet thrown.
+    fn throw(
+        &mut self,
+        others: &mut [Monkey],
+        relief: &Relief,
+        modulus: Option<u64>,
+    ) -> Result<(), u64> {
+        while let Some(level) = self.starting_items.pop_front() {
+            let Some(new_level) = self.operation.eval(level, modulus) else {
+                self.starting_items.push_front(level);
+                return Err(level);
+            };
+            let new_level = relief.apply(new_level);
+            let new_level = modulus.map_or(new_level, |modulus| new_level % modulus);
+            if new_level.is_multiple_of(self.test_divisor) {
+                others[self.test_branch.0].catch(new_level);
             } else {
-                others[self.test_branch.1].catch(level);
+                others[self.test_branch.1].catch(new_level);
             }
             self.thrown_items_counter += 1;
-        })
+        }
+        Ok(())
     }
-    pub fn catch(&mut self, item: u64) {
+    fn catch(&mut self, item: u64) {
         self.starting_items.push_back(item);
     }
 }
+
+/// How a monkey's worry level drops after it inspects an item and gets bored.
+pub enum Relief {
+    None,
+    DivideBy(u64),
+    Custom(Box<dyn Fn(u64) -> u64>),
+}
+
+impl Relief {
+    fn apply(&self, level: u64) -> u64 {
+        match self {
+            Self::None => level,
+            Self::DivideBy(k) => level / k,
+            Self::Custom(relief) => relief(level),
+        }
+    }
+}
+
+/// How worry levels are kept from overflowing between throws.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Modulus {
+    None,
+    /// Keeps levels modulo the least common multiple of all test divisors, which leaves every
+    /// test's outcome intact as long as the relief doesn't divide them.
+    #[default]
+    TestDivisors,
+    Fixed(u64),
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn checked_lcm(a: u64, b: u64) -> Option<u64> {
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+pub struct MonkeySim {
+    monkeys: Vec<Monkey>,
+    rounds: usize,
+    round: usize,
+    relief: Relief,
+    modulus: Option<u64>,
+}
+
+impl MonkeySim {
+    pub fn new(
+        monkeys: Vec<Monkey>,
+        rounds: usize,
+        relief: Relief,
+        modulus: Modulus,
+    ) -> Result<Self, InvalidSetup> {
+        if matches!(relief, Relief::DivideBy(0)) {
+            return Err(InvalidSetup::ZeroReliefDivisor);
+        }
+        let modulus = match modulus {
+            Modulus::None => None,
+            Modulus::TestDivisors => Some(
+                monkeys
+                    .iter()
+                    .try_fold(1, |lcm, monkey| checked_lcm(lcm, monkey.test_divisor))
+                    .ok_or(InvalidSetup::ModulusOverflow)?,
+            ),
+            Modulus::Fixed(modulus) => Some(modulus),
+        };
+        if modulus == Some(0) {
+            return Err(InvalidSetup::ZeroModulus);
+        }
+        Ok(Self {
+            monkeys,
+            rounds,
+            round: 0,
+            relief,
+            modulus,
+        })
+    }
+    pub fn monkeys(&self) -> &[Monkey] {
+        &self.monkeys
+    }
+    /// The number of rounds played so far.
+    pub fn round(&self) -> usize {
+        self.round
+    }
+    /// Plays the next round, returning the monkeys as they are at its end,
+    /// or `None` once all the rounds have been played.
+    pub fn step(&mut self) -> Result<Option<&[Monkey]>, WorryOverflow> {
+        if self.round >= self.rounds {
+            return Ok(None);
+        }
+        for monkey_idx in 0..self.monkeys.len() {
+            let mut curr_monkey = std::mem::take(&mut self.monkeys[monkey_idx]);
+            let thrown = curr_monkey.throw(&mut self.monkeys[..], &self.relief, self.modulus);
+            self.monkeys[monkey_idx] = curr_monkey;
+            thrown.map_err(|level| WorryOverflow {
+                monkey: monkey_idx,
+                level,
+            })?;
+        }
+        self.round += 1;
+
+        Ok(Some(&self.monkeys))
+    }
+    /// Plays all the remaining rounds.
+    pub fn run(&mut self) -> Result<&[Monkey], WorryOverflow> {
+        while self.step()?.is_some() {}
+        Ok(&self.monkeys)
+    }
+    /// The product of the two highest thrown items counters.
+    pub fn monkey_business(&self) -> usize {
+        self.monkeys
+            .iter()
+            .map(Monkey::thrown_items_counter)
+            .n_highest::<2>()
+            .product()
+    }
+}
 /// Monkey 0:
 ///   Starting items: 89, 95, 92, 64, 87, 68
 ///   Operation: new = old * 11
 ///   Test: divisible by 2
 ///     If true: throw to monkey 7
 ///     If false: throw to monkey 4
-fn parse_monkey(input: &str) -> IResult<&str, Monkey> {
-    let (starting_items, operation, (divisor, if_true_idx, if_false_idx)) = preceded(
-        delimited(tag("Monkey "), parse_u64, char(':')),
-        tuple((parse_starting_items, parse_operation, parse_test)),
-    )(input)?
-    .1;
-
-    Ok((
-        "",
-        Monkey {
-            starting_items,
-            operation,
-            test_divisor: divisor,
-            test_branch: (if_true_idx as usize, if_false_idx as usize),
-            thrown_items_counter: 0,
-        },
-    ))
+/// Parses the notes about the monkey at `idx`, which has to be numbered `idx` too.
+fn parse_monkey(input: &str, notes: &str, idx: usize) -> Result<Monkey, ParseError> {
+    let (rest, number) = expect(
+        input,
+        delimited(tag("Monkey "), parse_u64, char(':'))(notes),
+        "a `Monkey N:` header",
+    )?;
+    if number != idx as u64 {
+        return Err(ParseError::at(
+            11,
+            input,
+            &notes["Monkey ".len()..],
+            format!("monkey number {idx}"),
+        ));
+    }
+    let (rest, starting_items) = expect(
+        input,
+        parse_starting_items(rest),
+        "a list of starting items",
+    )?;
+    let (rest, operation) = expect(
+        input,
+        parse_operation(rest),
+        "an operation like `new = old * 3 + 2`",
+    )?;
+    let (rest, (divisor, if_true_idx, if_false_idx)) = expect(
+        input,
+        parse_test(rest),
+        "a divisibility test and where to throw to",
+    )?;
+    if !rest.trim().is_empty() {
+        return Err(ParseError::at(
+            11,
+            input,
+            rest.trim(),
+            "the end of the monkey notes",
+        ));
+    }
+
+    Ok(Monkey {
+        starting_items,
+        operation,
+        test_divisor: divisor,
+        test_branch: (if_true_idx as usize, if_false_idx as usize),
+        thrown_items_counter: 0,
+    })
+}
+
+/// Turns a nom failure into a `ParseError` pointing at where it stopped.
+fn expect<'i, T>(
+    input: &str,
+    result: IResult<&'i str, T>,
+    expected: &'static str,
+) -> Result<(&'i str, T), ParseError> {
+    result.map_err(|err| match err {
+        nom::Err::Error(err) | nom::Err::Failure(err) => {
+            ParseError::at(11, input, err.input, expected)
+        }
+        nom::Err::Incomplete(_) => ParseError::at_offset(11, input, input.len(), expected),
+    })
 }
 
 fn parse_starting_items(input: &str) -> IResult<&str, VecDeque<u64>> {
@@ -93,31 +353,51 @@ fn parse_starting_items(input: &str) -> IResult<&str, VecDeque<u64>> {
 }
 
 fn parse_operation(input: &str) -> IResult<&str, Operation> {
-    preceded(
-        tag("Operation: new = old "),
-        alt((
-            parse_operation_sum,
-            parse_operation_multiply,
-            parse_operation_square,
-        )),
+    delimited(
+        tag("Operation: new = "),
+        parse_expr,
+        pair(space0, alt((line_ending, eof))),
     )(input.trim())
 }
 
-fn parse_operation_square(input: &str) -> IResult<&str, Operation> {
-    tag("* old")(input).map(|(rem, _)| (rem, Operation::Square))
+/// expr := term (('+' | '-') term)*
+fn parse_expr(input: &str) -> IResult<&str, Operation> {
+    let (input, first) = parse_term(input)?;
+    fold_many0(
+        pair(delimited(space0, one_of("+-"), space0), parse_term),
+        move || first.clone(),
+        |lhs, (op, rhs)| match op {
+            '+' => Operation::Add(Box::new(lhs), Box::new(rhs)),
+            _ => Operation::Sub(Box::new(lhs), Box::new(rhs)),
+        },
+    )(input)
 }
 
-fn parse_operation_sum(input: &str) -> IResult<&str, Operation> {
-    preceded(tag("+ "), parse_u64)(input).map(|(rem, value)| (rem, Operation::Sum(value)))
+/// term := atom ('*' atom)*
+fn parse_term(input: &str) -> IResult<&str, Operation> {
+    let (input, first) = parse_atom(input)?;
+    fold_many0(
+        preceded(delimited(space0, char('*'), space0), parse_atom),
+        move || first.clone(),
+        |lhs, rhs| Operation::Mul(Box::new(lhs), Box::new(rhs)),
+    )(input)
 }
 
-fn parse_operation_multiply(input: &str) -> IResult<&str, Operation> {
-    preceded(tag("* "), parse_u64)(input).map(|(rem, value)| (rem, Operation::Multiply(value)))
+/// atom := 'old' | number | '(' expr ')'
+fn parse_atom(input: &str) -> IResult<&str, Operation> {
+    alt((
+        value(Operation::Old, tag("old")),
+        map(parse_u64, Operation::Const),
+        delimited(pair(char('('), space0), parse_expr, pair(space0, char(')'))),
+    ))(input)
 }
 
 fn parse_test(input: &str) -> IResult<&str, (u64, u64, u64)> {
     tuple((
-        preceded(tag("Test: divisible by "), parse_u64),
+        preceded(
+            tag("Test: divisible by "),
+            verify(parse_u64, |divisor| *divisor != 0),
+        ),
         preceded(
             multispace1,
             preceded(tag("If true: throw to monkey "), parse_u64),
@@ -129,49 +409,154 @@ fn parse_test(input: &str) -> IResult<&str, (u64, u64, u64)> {
     ))(input.trim())
 }
 
-fn parse(input: &str) -> Vec<Monkey> {
-    input
-        .split("\n\n")
-        .flat_map(|monkey_str| parse_monkey(monkey_str).map(|(_rem, monkey)| monkey))
+#[aoc_generator(day11)]
+pub fn generator(input: &str) -> Result<Vec<Monkey>, ParseError> {
+    let notes = input.split("\n\n").collect::<Vec<_>>();
+    notes
+        .iter()
+        .enumerate()
+        .map(|(idx, monkey_notes)| {
+            let monkey = parse_monkey(input, monkey_notes, idx)?;
+            let (if_true_idx, if_false_idx) = monkey.test_branch;
+            if if_true_idx.max(if_false_idx) >= notes.len() {
+                return Err(ParseError::at(
+                    11,
+                    input,
+                    monkey_notes,
+                    format!("branches to monkeys between 0 and {}", notes.len() - 1),
+                ));
+            }
+            // A monkey can't catch the items it's throwing, since it's holding them.
+            if if_true_idx == idx || if_false_idx == idx {
+                return Err(ParseError::at(
+                    11,
+                    input,
+                    monkey_notes,
+                    format!("branches to monkeys other than {idx}"),
+                ));
+            }
+            Ok(monkey)
+        })
         .collect()
 }
 
 #[aoc(day11, part1)]
-fn part1(input: &str) -> usize {
-    let mut monkeys = parse(input);
-    let divider: u64 = monkeys.iter().map(|monkey| monkey.test_divisor).product();
-
-    for _round in 0..20 {
-        for monkey_idx in 0..monkeys.len() {
-            let mut curr_monkey = std::mem::take(&mut monkeys[monkey_idx]);
-            curr_monkey.throw(&mut monkeys[..], true, divider);
-            monkeys[monkey_idx] = curr_monkey;
-        }
-    }
-
-    monkeys
-        .into_iter()
-        .map(|monkey| monkey.thrown_items_counter)
-        .n_highest::<2>()
-        .product()
+fn part1(input: &[Monkey]) -> Result<usize, Box<dyn Error>> {
+    let mut sim = MonkeySim::new(input.to_vec(), 20, Relief::DivideBy(3), Modulus::None)?;
+    sim.run()?;
+    Ok(sim.monkey_business())
 }
 
 #[aoc(day11, part2)]
-fn part2(input: &str) -> usize {
-    let mut monkeys = parse(input);
-    let divider: u64 = monkeys.iter().map(|monkey| monkey.test_divisor).product();
-
-    for _round in 0..10_000 {
-        for monkey_idx in 0..monkeys.len() {
-            let mut curr_monkey = std::mem::take(&mut monkeys[monkey_idx]);
-            curr_monkey.throw(&mut monkeys[..], false, divider);
-            monkeys[monkey_idx] = curr_monkey;
-        }
+fn part2(input: &[Monkey]) -> Result<usize, Box<dyn Error>> {
+    let mut sim = MonkeySim::new(input.to_vec(), 10_000, Relief::None, Modulus::TestDivisors)?;
+    sim.run()?;
+    Ok(sim.monkey_business())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAIR: &str = "\
+Monkey 0:
+  Starting items: 79, 98
+  Operation: new = old * 19
+  Test: divisible by 23
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items: 54
+  Operation: new = old + 6
+  Test: divisible by 19
+    If true: throw to monkey 0
+    If false: throw to monkey 0";
+
+    #[test]
+    fn rejects_self_throws() {
+        assert!(generator(PAIR).is_ok());
+        let err =
+            generator(&PAIR.replacen("throw to monkey 1", "throw to monkey 0", 1)).unwrap_err();
+        assert_eq!(
+            (err.line(), err.expected()),
+            (1, "branches to monkeys other than 0")
+        );
+    }
+
+    #[test]
+    fn rejects_zero_divisors() {
+        let monkeys = generator(PAIR).unwrap();
+        let sim = MonkeySim::new(monkeys.clone(), 20, Relief::DivideBy(0), Modulus::None);
+        assert_eq!(sim.err(), Some(InvalidSetup::ZeroReliefDivisor));
+        let sim = MonkeySim::new(monkeys, 20, Relief::None, Modulus::Fixed(0));
+        assert_eq!(sim.err(), Some(InvalidSetup::ZeroModulus));
     }
 
-    monkeys
-        .into_iter()
-        .map(|monkey| monkey.thrown_items_counter)
-        .n_highest::<2>()
-        .product()
+    #[test]
+    fn monkey_numbers() {
+        let swapped = PAIR
+            .replace("Monkey 0", "Monkey 2")
+            .replace("Monkey 1", "Monkey 0");
+        let err = generator(&swapped).unwrap_err();
+        assert_eq!((err.line(), err.column()), (1, 8));
+        assert_eq!(err.expected(), "monkey number 0");
+    }
+
+    fn operation(expr: &str) -> Option<Operation> {
+        let (_, operation) = parse_operation(&format!("Operation: new = {expr}")).ok()?;
+        Some(operation)
+    }
+
+    #[test]
+    fn operations() {
+        let eval = |expr, old| operation(expr).unwrap().eval(old, None);
+        assert_eq!(eval("old * 3 + 2", 5), Some(17));
+        assert_eq!(eval("2 + old * 3", 5), Some(17));
+        assert_eq!(eval("old * (3 + 2)", 5), Some(25));
+        assert_eq!(eval("((old))*old", 5), Some(25));
+        assert_eq!(eval("4 * old", 5), Some(20));
+        assert_eq!(eval("old - 3 - 1", 5), Some(1));
+        assert_eq!(eval("old * old", u64::MAX), None);
+        assert!(operation("old ^ 2").is_none());
+        assert!(operation("old *").is_none());
+        assert!(operation("(old + 1").is_none());
+    }
+
+    #[test]
+    fn subtraction_underflow() {
+        let sub = operation("old - 7").unwrap();
+        assert_eq!(sub.eval(5, None), None);
+        assert_eq!(sub.eval(5, Some(10)), Some(8));
+        assert_eq!(sub.eval(7, Some(10)), Some(0));
+    }
+
+    /// Notes for monkeys throwing to the next one, with the given test divisors.
+    fn troop(divisors: &[u64]) -> Vec<Monkey> {
+        let notes = divisors
+            .iter()
+            .enumerate()
+            .map(|(idx, divisor)| {
+                let next = (idx + 1) % divisors.len();
+                format!(
+                    "Monkey {idx}:\n  Starting items: 1\n  Operation: new = old + 1\n  \
+                     Test: divisible by {divisor}\n    If true: throw to monkey {next}\n    \
+                     If false: throw to monkey {next}"
+                )
+            })
+            .collect::<Vec<_>>();
+        generator(&notes.join("\n\n")).unwrap()
+    }
+
+    #[test]
+    fn test_divisors_modulus() {
+        // Shared factors are only counted once.
+        let sim = MonkeySim::new(troop(&[6, 10, 15]), 1, Relief::None, Modulus::TestDivisors);
+        assert_eq!(sim.unwrap().modulus, Some(30));
+        let big = [4_294_967_291, 4_294_967_279, 4_294_967_231];
+        let sim = MonkeySim::new(troop(&big), 1, Relief::None, Modulus::TestDivisors);
+        assert_eq!(sim.err(), Some(InvalidSetup::ModulusOverflow));
+        let sim = MonkeySim::new(troop(&big[..2]), 1, Relief::None, Modulus::TestDivisors);
+        assert!(sim.is_ok());
+    }
 }
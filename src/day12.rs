@@ -1,96 +1,433 @@
-use pathfinding::directed::bfs::bfs;
-use std::{ops::Index, str::FromStr};
-
-const START_POS: (usize, usize) = (20, 0);
-const END_POS: (usize, usize) = (20, 43);
+use crate::{
+    utils::grid::{Grid, Pos},
+    ParseError,
+};
+use pathfinding::directed::{astar::astar, bfs::bfs, dijkstra::dijkstra};
+use std::{cmp::Ordering, collections::VecDeque, ops::Index, str::FromStr};
 
 #[derive(Debug)]
-struct HeatMap {
-    rows: usize,
-    cols: usize,
-    values: Vec<i8>,
+pub struct HeatMap {
+    heights: Grid<i8>,
+    start: Pos,
+    end: Pos,
+}
+
+impl HeatMap {
+    pub fn heights(&self) -> &Grid<i8> {
+        &self.heights
+    }
+    pub fn start(&self) -> Pos {
+        self.start
+    }
+    pub fn end(&self) -> Pos {
+        self.end
+    }
+    /// The hike from `S` to `E` with the fewest steps, under the puzzle's rule.
+    pub fn shortest_hike(&self) -> Option<Hike> {
+        ClimbQuery::new(self, ClimbRule::PUZZLE, Cost::Unit).cheapest_hike(
+            self.start,
+            self.end,
+            Algorithm::Bfs,
+        )
+    }
+    /// The hike to `E` with the fewest steps from any of the lowest positions, under the puzzle's rule.
+    pub fn shortest_hike_from_lowest(&self) -> Option<Hike> {
+        ClimbQuery::new(self, ClimbRule::PUZZLE, Cost::Unit).cheapest_hike_from(
+            |pos| self[pos] == 0,
+            self.end,
+            Algorithm::Bfs,
+        )
+    }
 }
 
-impl Index<(usize, usize)> for HeatMap {
+impl Index<Pos> for HeatMap {
     type Output = i8;
-    fn index(&self, (row, col): (usize, usize)) -> &Self::Output {
-        &self.values[row * self.cols + col]
+    fn index(&self, pos: Pos) -> &Self::Output {
+        &self.heights[pos]
     }
 }
 
 impl FromStr for HeatMap {
-    type Err = ();
+    type Err = ParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let cols = s.find("\n").unwrap();
-        let values: Vec<i8> = s
-            .lines()
-            .flat_map(str::chars)
-            .map(|c| match c {
-                'S' => -1,
-                'E' => (b'z' - b'a' + 1) as i8,
-                c @ 'a'..='z' => c as i8 - b'a' as i8,
-                _ => unreachable!(),
-            })
-            .collect();
-        let rows = values.len() / cols;
-        Ok(Self { cols, rows, values })
-    }
-}
-
-fn adjacents(
-    pos: (usize, usize),
-    rows: usize,
-    cols: usize,
-) -> impl Iterator<Item = (usize, usize)> {
-    let (i, j) = pos;
-    const CARDINALS: [(isize, isize); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
-    CARDINALS
-        .into_iter()
-        .flat_map(move |(r, c)| {
-            (i as isize + r)
-                .try_into()
-                .and_then(|i| (j as isize + c).try_into().map(|j| (i, j)))
+        let (mut start, mut end) = (None, None);
+        let heights = Grid::parse(12, s, |pos, c| match c {
+            // `S` and `E` are at the same elevation as `a` and `z`.
+            'S' if start.is_some() => Err("a height (a-z) or `E`, after the only `S`"),
+            'E' if end.is_some() => Err("a height (a-z) or `S`, after the only `E`"),
+            'S' => {
+                start = Some(pos);
+                Ok(0)
+            }
+            'E' => {
+                end = Some(pos);
+                Ok((b'z' - b'a') as i8)
+            }
+            c @ 'a'..='z' => Ok(c as i8 - b'a' as i8),
+            _ => Err("a height (a-z), `S` or `E`"),
+        })?;
+        let missing = |expected| ParseError::at_offset(12, s, s.len(), expected);
+        Ok(Self {
+            heights,
+            start: start.ok_or_else(|| missing("a start position `S`"))?,
+            end: end.ok_or_else(|| missing("a best signal position `E`"))?,
+        })
+    }
+}
+
+/// Which steps a hiker is willing to take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClimbRule {
+    pub max_climb: i8,
+    /// `None` for descending any height at once, `Some(0)` for never going down.
+    pub max_descent: Option<i8>,
+}
+
+impl ClimbRule {
+    /// Up by at most one level, down as much as wanted.
+    pub const PUZZLE: Self = Self {
+        max_climb: 1,
+        max_descent: None,
+    };
+    pub fn allows(&self, from_height: i8, to_height: i8) -> bool {
+        let climb = to_height - from_height;
+        climb <= self.max_climb && self.max_descent.is_none_or(|max| -climb <= max)
+    }
+}
+
+impl Default for ClimbRule {
+    fn default() -> Self {
+        Self::PUZZLE
+    }
+}
+
+/// How much a single step costs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Cost {
+    /// Every step costs 1, like the puzzle.
+    #[default]
+    Unit,
+    /// A step costs 1, plus this much for each level it goes up or down.
+    PerLevel(u32),
+}
+
+impl Cost {
+    fn of(&self, from_height: i8, to_height: i8) -> u32 {
+        match self {
+            Self::Unit => 1,
+            Self::PerLevel(per_level) => 1 + per_level * from_height.abs_diff(to_height) as u32,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Finds the hike with the fewest steps, regardless of their cost.
+    #[default]
+    Bfs,
+    Dijkstra,
+    /// Dijkstra, guided by the Manhattan distance plus the cost of the levels left to climb.
+    AStar,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hike {
+    /// Every position along the hike, both ends included.
+    pub path: Vec<Pos>,
+    pub cost: u32,
+}
+
+impl Hike {
+    pub fn steps(&self) -> usize {
+        self.path.len().saturating_sub(1)
+    }
+    /// Draws the hike over `map` like the puzzle does: every step is an arrow
+    /// (`>`, `v`, `<`, `^`) pointing to the next one, the end is `E` and the rest is `.`.
+    pub fn render(&self, map: &HeatMap) -> String {
+        let mut cells = map.heights.map(|_| '.');
+        if let Some(last) = self.path.last() {
+            cells[*last] = 'E';
+        }
+        for step in self.path.windows(2) {
+            let ((row, col), (next_row, next_col)) = (step[0], step[1]);
+            cells[step[0]] = match (next_row.cmp(&row), next_col.cmp(&col)) {
+                (_, Ordering::Greater) => '>',
+                (Ordering::Greater, _) => 'v',
+                (_, Ordering::Less) => '<',
+                _ => '^',
+            };
+        }
+        cells.render(|_, c| *c)
+    }
+}
+
+/// Looks for the cheapest hikes on a `HeatMap`, under some climbing rule and step cost.
+#[derive(Debug, Clone, Copy)]
+pub struct ClimbQuery<'m> {
+    map: &'m HeatMap,
+    rule: ClimbRule,
+    cost: Cost,
+}
+
+impl<'m> ClimbQuery<'m> {
+    pub fn new(map: &'m HeatMap, rule: ClimbRule, cost: Cost) -> Self {
+        Self { map, rule, cost }
+    }
+    /// The positions reachable with a single step from `pos`, along with their cost.
+    pub fn successors(&self, pos: Pos) -> impl Iterator<Item = (Pos, u32)> + 'm {
+        let Self { map, rule, cost } = *self;
+        map.heights
+            .neighbours4(pos)
+            .filter(move |next| rule.allows(map[pos], map[*next]))
+            .map(move |next| (next, cost.of(map[pos], map[next])))
+    }
+    /// The positions `pos` is reachable from with a single step, along with their cost.
+    pub fn predecessors(&self, pos: Pos) -> impl Iterator<Item = (Pos, u32)> + 'm {
+        let Self { map, rule, cost } = *self;
+        map.heights
+            .neighbours4(pos)
+            .filter(move |prev| rule.allows(map[*prev], map[pos]))
+            .map(move |prev| (prev, cost.of(map[prev], map[pos])))
+    }
+    fn path_cost(&self, path: &[Pos]) -> u32 {
+        path.windows(2)
+            .map(|step| self.cost.of(self.map[step[0]], self.map[step[1]]))
+            .sum()
+    }
+    fn heuristic(&self, pos: Pos, to: Pos) -> u32 {
+        let manhattan = (pos.0.abs_diff(to.0) + pos.1.abs_diff(to.1)) as u32;
+        let levels = self.map[pos].abs_diff(self.map[to]) as u32;
+        match self.cost {
+            Cost::Unit => manhattan,
+            Cost::PerLevel(per_level) => manhattan + per_level * levels,
+        }
+    }
+    pub fn cheapest_hike(&self, from: Pos, to: Pos, algorithm: Algorithm) -> Option<Hike> {
+        match algorithm {
+            Algorithm::Bfs => bfs(
+                &from,
+                |pos| self.successors(*pos).map(|(next, _)| next),
+                |pos| *pos == to,
+            )
+            .map(|path| Hike {
+                cost: self.path_cost(&path),
+                path,
+            }),
+            Algorithm::Dijkstra => dijkstra(&from, |pos| self.successors(*pos), |pos| *pos == to)
+                .map(|(path, cost)| Hike { path, cost }),
+            Algorithm::AStar => astar(
+                &from,
+                |pos| self.successors(*pos),
+                |pos| self.heuristic(*pos, to),
+                |pos| *pos == to,
+            )
+            .map(|(path, cost)| Hike { path, cost }),
+        }
+    }
+    /// How many steps away from `to` every position is, ignoring their cost.
+    pub fn distance_map(&self, to: Pos) -> DistanceMap {
+        let mut steps = self.map.heights.map(|_| None);
+        let mut queue = VecDeque::from([(to, 0)]);
+        steps[to] = Some(0);
+        while let Some((pos, distance)) = queue.pop_front() {
+            for (prev, _) in self.predecessors(pos) {
+                if steps[prev].is_none() {
+                    steps[prev] = Some(distance + 1);
+                    queue.push_back((prev, distance + 1));
+                }
+            }
+        }
+        DistanceMap { target: to, steps }
+    }
+    /// The cheapest hike to `to` from any position matching `is_start`, searching
+    /// backwards from `to`. A* has no single goal to aim for here, so it runs as Dijkstra.
+    pub fn cheapest_hike_from<F>(&self, is_start: F, to: Pos, algorithm: Algorithm) -> Option<Hike>
+    where
+        F: Fn(Pos) -> bool,
+    {
+        let path = match algorithm {
+            Algorithm::Bfs => bfs(
+                &to,
+                |pos| self.predecessors(*pos).map(|(prev, _)| prev),
+                |pos| is_start(*pos),
+            ),
+            Algorithm::Dijkstra | Algorithm::AStar => {
+                dijkstra(&to, |pos| self.predecessors(*pos), |pos| is_start(*pos))
+                    .map(|(path, _)| path)
+            }
+        };
+        path.map(|mut path| {
+            path.reverse();
+            Hike {
+                cost: self.path_cost(&path),
+                path,
+            }
         })
-        .filter(move |pos| pos.0 < rows && pos.1 < cols)
+    }
+}
+
+/// The number of steps from every position to a target, following the reverse
+/// of a `ClimbQuery`'s steps outward from the target.
+#[derive(Debug, Clone)]
+pub struct DistanceMap {
+    target: Pos,
+    steps: Grid<Option<usize>>,
+}
+
+impl DistanceMap {
+    /// The distances to `E` under the puzzle's rule.
+    pub fn new(map: &HeatMap) -> Self {
+        ClimbQuery::new(map, ClimbRule::PUZZLE, Cost::Unit).distance_map(map.end)
+    }
+    pub fn target(&self) -> Pos {
+        self.target
+    }
+    /// How many steps it takes to reach the target from `pos`, if it can be reached at all.
+    pub fn distance(&self, pos: Pos) -> Option<usize> {
+        self.steps.get(pos).copied().flatten()
+    }
+    /// The position matching `predicate` that is closest to the target, with its distance.
+    pub fn nearest<P: Fn(Pos) -> bool>(&self, predicate: P) -> Option<(Pos, usize)> {
+        self.steps
+            .iter()
+            .filter(|(pos, _)| predicate(*pos))
+            .filter_map(|(pos, steps)| Some((pos, (*steps)?)))
+            .min_by_key(|(_, steps)| *steps)
+    }
+    /// Draws `map` with shades going from ` ` for the lowest positions to `@` for
+    /// the highest ones. The target is `E`, and positions that can't reach it are `?`.
+    pub fn render(&self, map: &HeatMap) -> String {
+        const SHADES: &[u8] = b" .:-=+*#%@";
+        let highest = (b'z' - b'a') as usize;
+        map.heights.render(|pos, height| match self.distance(pos) {
+            _ if pos == self.target => 'E',
+            Some(_) => SHADES[*height as usize * (SHADES.len() - 1) / highest] as char,
+            None => '?',
+        })
+    }
 }
 
 #[aoc_generator(day12)]
 #[inline(always)]
-fn generator(input: &str) -> HeatMap {
-    HeatMap::from_str(input).unwrap()
+fn generator(input: &str) -> Result<HeatMap, ParseError> {
+    HeatMap::from_str(input)
 }
 
 #[aoc(day12, part1)]
-fn part1(input: &HeatMap) -> usize {
-    bfs(
-        &END_POS,
-        |&curr_pos| {
-            adjacents(curr_pos, input.rows, input.cols).filter(move |pos| {
-                let starting_height = input[curr_pos];
-                let dest_height = input[*pos];
-                starting_height - dest_height <= 1
-            })
-        },
-        |pos| *pos == START_POS,
-    )
-    .map(|path| path.len())
-    .unwrap() - 1
+fn part1(input: &HeatMap) -> Option<usize> {
+    DistanceMap::new(input).distance(input.start)
+}
+
+#[aoc(day12, part1, Hike)]
+fn part1_hike(input: &HeatMap) -> Option<usize> {
+    input.shortest_hike().map(|hike| hike.steps())
+}
+
+#[aoc(day12, part1, AStar)]
+fn part1_astar(input: &HeatMap) -> Option<u32> {
+    ClimbQuery::new(input, ClimbRule::PUZZLE, Cost::Unit)
+        .cheapest_hike(input.start, input.end, Algorithm::AStar)
+        .map(|hike| hike.cost)
 }
 
 #[aoc(day12, part2)]
-fn part2(input: &HeatMap) -> usize {
-    bfs(
-        &END_POS,
-        |&curr_pos| {
-            adjacents(curr_pos, input.rows, input.cols).filter(move |pos| {
-                let starting_height = input[curr_pos];
-                let dest_height = input[*pos];
-                starting_height - dest_height <= 1
-            })
-        },
-        |pos| input[*pos] == 0,
-    )
-    .map(|path| path.len())
-    .unwrap() - 1
+fn part2(input: &HeatMap) -> Option<usize> {
+    DistanceMap::new(input)
+        .nearest(|pos| input[pos] == 0)
+        .map(|(_, steps)| steps)
+}
+
+#[aoc(day12, part2, Hike)]
+fn part2_hike(input: &HeatMap) -> Option<usize> {
+    input.shortest_hike_from_lowest().map(|hike| hike.steps())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Sabqponm\nabcryxxl\naccszExk\nacctuvwj\nabdefghi";
+
+    #[test]
+    fn example() {
+        let map = generator(EXAMPLE).unwrap();
+        assert_eq!(part1(&map), Some(31));
+        assert_eq!(part2(&map), Some(29));
+        assert_eq!(part1_hike(&map), Some(31));
+        assert_eq!(part2_hike(&map), Some(29));
+        assert_eq!(part1_astar(&map), Some(31));
+    }
+
+    #[test]
+    fn start_and_end_are_a_and_z() {
+        let map = generator("SbE").unwrap();
+        assert_eq!(part1(&map), None);
+        let map = generator("SabcdefghijklmnopqrstuvwxyE").unwrap();
+        assert_eq!(part1(&map), Some(26));
+        assert_eq!(part2(&map), Some(25));
+        // `S` is the only lowest position, so part 2 has to start from it.
+        let map = generator("SbcdefghijklmnopqrstuvwxyE").unwrap();
+        assert_eq!(part1(&map), Some(25));
+        assert_eq!(part2(&map), Some(25));
+    }
+
+    #[test]
+    fn single_start_and_end() {
+        let err = generator("SaS\nabE").unwrap_err();
+        assert_eq!((err.line(), err.column()), (1, 3));
+        let err = generator("SaE\nabE").unwrap_err();
+        assert_eq!((err.line(), err.column()), (2, 3));
+        assert!(generator("aaE").is_err());
+        assert!(generator("Saa").is_err());
+    }
+
+    #[test]
+    fn example_hike() {
+        let map = generator(EXAMPLE).unwrap();
+        let hike = map.shortest_hike().unwrap();
+        assert_eq!(hike.steps(), 31);
+        assert_eq!(hike.path.first(), Some(&map.start()));
+        assert_eq!(hike.path.last(), Some(&map.end()));
+        for step in hike.path.windows(2) {
+            assert!(map.heights().neighbours4(step[0]).any(|pos| pos == step[1]));
+            assert!(ClimbRule::PUZZLE.allows(map[step[0]], map[step[1]]));
+        }
+
+        // BFS may pick another of the shortest hikes than the puzzle's illustration,
+        // so only the shape of the drawing is checked.
+        let rendered = hike.render(&map);
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert!(lines.iter().all(|line| line.len() == 8));
+        for (row, line) in lines.iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                let on_path = hike.path.contains(&(row, col));
+                match c {
+                    'E' => assert_eq!((row, col), map.end()),
+                    '>' | 'v' | '<' | '^' => assert!(on_path),
+                    '.' => assert!(!on_path),
+                    _ => panic!("unexpected {c:?} in\n{rendered}"),
+                }
+            }
+        }
+        let arrows = rendered.chars().filter(|c| "<>^v".contains(*c)).count();
+        assert_eq!(arrows, 31);
 
+        assert_eq!(map.shortest_hike_from_lowest().unwrap().steps(), 29);
+    }
+
+    #[test]
+    fn distance_map_render() {
+        let map = generator(EXAMPLE).unwrap();
+        let distances = DistanceMap::new(&map);
+        assert_eq!(distances.distance(map.start()), Some(31));
+        assert_eq!(
+            distances.render(&map),
+            "   +++==\n   *%%%-\n   *@E%-\n   *###-\n  ...:::\n"
+        );
+
+        // Only a `y` or a `z` can climb to `E`.
+        let map = generator("SbE\nbyz").unwrap();
+        assert_eq!(DistanceMap::new(&map).render(&map), "??E\n?%@\n");
+    }
 }
@@ -1,3 +1,5 @@
+use crate::ParseError;
+
 trait AsPoints {
     fn as_points(&self) -> u32;
 }
@@ -42,13 +44,14 @@ impl AsPoints for Sign {
     }
 }
 
-impl From<u8> for Sign {
-    fn from(c: u8) -> Self {
+impl TryFrom<u8> for Sign {
+    type Error = &'static str;
+    fn try_from(c: u8) -> Result<Self, Self::Error> {
         match c {
-            b'A' | b'X' => Self::Rock,
-            b'B' | b'Y' => Self::Paper,
-            b'C' | b'Z' => Self::Scissor,
-            _ => unreachable!(),
+            b'A' | b'X' => Ok(Self::Rock),
+            b'B' | b'Y' => Ok(Self::Paper),
+            b'C' | b'Z' => Ok(Self::Scissor),
+            _ => Err("a sign (A, B, C, X, Y or Z)"),
         }
     }
 }
@@ -81,26 +84,41 @@ impl AsPoints for Outcome {
     }
 }
 
-impl From<u8> for Outcome {
-    fn from(c: u8) -> Self {
+impl TryFrom<u8> for Outcome {
+    type Error = &'static str;
+    fn try_from(c: u8) -> Result<Self, Self::Error> {
         match c {
-            b'X' => Self::Lose,
-            b'Y' => Self::Draw,
-            b'Z' => Self::Win,
-            _ => unreachable!(),
+            b'X' => Ok(Self::Lose),
+            b'Y' => Ok(Self::Draw),
+            b'Z' => Ok(Self::Win),
+            _ => Err("an outcome (X, Y or Z)"),
         }
     }
 }
 
+/// Parses a line like `A Y` into the opponent's sign and the second column.
+fn parse_round<T>(input: &str, line: &str) -> Result<(Sign, T), ParseError>
+where
+    T: TryFrom<u8, Error = &'static str>,
+{
+    let err = |byte: usize, expected| ParseError::at_byte(2, input, line, byte, expected);
+    let bytes = line.as_bytes();
+    let byte_at = |i: usize| bytes.get(i).copied().unwrap_or(b'\n');
+    let other = Sign::try_from(byte_at(0)).map_err(|expected| err(0, expected))?;
+    if byte_at(1) != b' ' {
+        return Err(err(1, "a space"));
+    }
+    let second = T::try_from(byte_at(2)).map_err(|expected| err(2, expected))?;
+    if bytes.len() > 3 {
+        return Err(err(3, "the end of the line"));
+    }
+
+    Ok((other, second))
+}
+
 #[aoc_generator(day2, part1)]
-fn generator(input: &str) -> Vec<(Sign, Sign)> {
-    input
-        .lines()
-        .map(|line| {
-            let bytes = line.as_bytes();
-            (bytes[0].into(), bytes[2].into())
-        })
-        .collect()
+fn generator(input: &str) -> Result<Vec<(Sign, Sign)>, ParseError> {
+    input.lines().map(|line| parse_round(input, line)).collect()
 }
 
 #[aoc(day2, part1)]
@@ -112,14 +130,8 @@ fn part1(input: &[(Sign, Sign)]) -> u32 {
 }
 
 #[aoc_generator(day2, part2)]
-fn generator2(input: &str) -> Vec<(Sign, Outcome)> {
-    input
-        .lines()
-        .map(|line| {
-            let bytes = line.as_bytes();
-            (bytes[0].into(), bytes[2].into())
-        })
-        .collect()
+fn generator2(input: &str) -> Result<Vec<(Sign, Outcome)>, ParseError> {
+    input.lines().map(|line| parse_round(input, line)).collect()
 }
 
 #[aoc(day2, part2)]
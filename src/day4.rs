@@ -1,50 +1,449 @@
-use std::ops::RangeInclusive;
+use crate::{utils::split_number, ParseError};
+use std::{
+    iter,
+    ops::{Range, RangeInclusive},
+    str::FromStr,
+};
 
-trait RangeOverlap {
+/// Overlaps between ranges of sections. Empty and reversed ranges like `5-3` contain
+/// no sections: they overlap nothing, and are fully overlapped by any range.
+pub trait RangeOverlap {
     fn overlaps(&self, other: &Self) -> bool;
+    /// Whether every section of `other` is also in `self`.
     fn fully_overlaps(&self, other: &Self) -> bool;
 }
 
-impl RangeOverlap for RangeInclusive<u32> {
+impl<T: Ord> RangeOverlap for RangeInclusive<T> {
     fn fully_overlaps(&self, other: &Self) -> bool {
-        self.start() <= other.start() && self.end() >= other.end()
+        other.is_empty() || (self.start() <= other.start() && self.end() >= other.end())
     }
     fn overlaps(&self, other: &Self) -> bool {
-        self.contains(other.start()) || other.contains(self.start())
+        !self.is_empty()
+            && !other.is_empty()
+            && self.start() <= other.end()
+            && other.start() <= self.end()
     }
 }
 
-fn parse_range(s: &str) -> RangeInclusive<u32> {
-    let (left, right) = s.split_once('-').unwrap();
-    let left = left.parse::<u32>().unwrap();
-    let right = right.parse::<u32>().unwrap();
+impl<T: Ord> RangeOverlap for Range<T> {
+    fn fully_overlaps(&self, other: &Self) -> bool {
+        other.is_empty() || (self.start <= other.start && self.end >= other.end)
+    }
+    fn overlaps(&self, other: &Self) -> bool {
+        !self.is_empty() && !other.is_empty() && self.start < other.end && other.start < self.end
+    }
+}
+
+pub type Assignments<T = u32> = (RangeInclusive<T>, RangeInclusive<T>);
 
-    left..=right
+/// Parses a range like `2-4`, or `-4--2` for signed sections, off the start of `s`,
+/// returning what's left of it.
+fn parse_range<'i, T: FromStr>(
+    input: &str,
+    s: &'i str,
+) -> Result<(RangeInclusive<T>, &'i str), ParseError> {
+    let (left, rest) = split_number::<T>(s)
+        .ok_or_else(|| ParseError::at(4, input, s, "the first section of a range"))?;
+    let rest = rest
+        .strip_prefix('-')
+        .ok_or_else(|| ParseError::at(4, input, rest, "`-`"))?;
+    let (right, rest) = split_number::<T>(rest)
+        .ok_or_else(|| ParseError::at(4, input, rest, "the last section of a range"))?;
+
+    Ok((left..=right, rest))
 }
 
-#[aoc_generator(day4)]
-fn generator(input: &str) -> Vec<(RangeInclusive<u32>, RangeInclusive<u32>)> {
+/// A set of sections, stored as sorted ranges that neither overlap nor touch.
+/// Empty and reversed ranges like `5-3` contain no sections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    ranges: Vec<RangeInclusive<u32>>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn ranges(&self) -> &[RangeInclusive<u32>] {
+        &self.ranges
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+    /// How many sections the set covers.
+    pub fn len(&self) -> u64 {
+        self.ranges
+            .iter()
+            .map(|range| (range.end() - range.start()) as u64 + 1)
+            .sum()
+    }
+    pub fn contains(&self, section: u32) -> bool {
+        let i = self.ranges.partition_point(|range| *range.end() < section);
+        self.ranges
+            .get(i)
+            .is_some_and(|range| range.contains(&section))
+    }
+    pub fn insert(&mut self, range: RangeInclusive<u32>) {
+        *self = self
+            .ranges
+            .iter()
+            .cloned()
+            .chain(iter::once(range))
+            .collect();
+    }
+    pub fn union(&self, other: &Self) -> Self {
+        self.ranges.iter().chain(&other.ranges).cloned().collect()
+    }
+    pub fn intersection(&self, other: &Self) -> Self {
+        let (mut i, mut j) = (0, 0);
+        let mut ranges = vec![];
+        while let (Some(a), Some(b)) = (self.ranges.get(i), other.ranges.get(j)) {
+            let start = *a.start().max(b.start());
+            let end = *a.end().min(b.end());
+            if start <= end {
+                ranges.push(start..=end);
+            }
+            if a.end() < b.end() {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        Self { ranges }
+    }
+    pub fn difference(&self, other: &Self) -> Self {
+        self.intersection(&other.complement())
+    }
+    /// Every section that isn't in the set.
+    pub fn complement(&self) -> Self {
+        let mut ranges = vec![];
+        let mut next = Some(0);
+        for range in &self.ranges {
+            if let Some(start) = next.filter(|start| start < range.start()) {
+                ranges.push(start..=range.start() - 1);
+            }
+            next = range.end().checked_add(1);
+        }
+        if let Some(start) = next {
+            ranges.push(start..=u32::MAX);
+        }
+        Self { ranges }
+    }
+    /// The sections covered by at least `k` of `ranges`, counting overlapping ones
+    /// as many times as they appear.
+    pub fn covered_at_least<I>(ranges: I, k: usize) -> Self
+    where
+        I: IntoIterator<Item = RangeInclusive<u32>>,
+    {
+        if k == 0 {
+            return Self::from(0..=u32::MAX);
+        }
+        // Sections past the end of a range are counted in u64, in case it ends at u32::MAX.
+        let mut events = ranges
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .flat_map(|range| [(*range.start() as u64, 1), (*range.end() as u64 + 1, -1)])
+            .collect::<Vec<_>>();
+        events.sort_unstable();
+        let mut covering = 0_isize;
+        let mut start = None;
+        let mut ranges = vec![];
+        for (section, change) in events {
+            covering += change;
+            match start {
+                None if covering >= k as isize => start = Some(section),
+                Some(from) if covering < k as isize => {
+                    start = None;
+                    if from < section {
+                        ranges.push(from as u32..=(section - 1) as u32);
+                    }
+                }
+                _ => (),
+            }
+        }
+        ranges.into_iter().collect()
+    }
+}
+
+/// Merges the ranges, skipping empty ones.
+impl FromIterator<RangeInclusive<u32>> for IntervalSet {
+    fn from_iter<I: IntoIterator<Item = RangeInclusive<u32>>>(iter: I) -> Self {
+        let mut ranges = iter
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_unstable_by_key(|range| *range.start());
+        let mut merged: Vec<RangeInclusive<u32>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if *range.start() <= last.end().saturating_add(1) => {
+                    *last = *last.start()..=*last.end().max(range.end());
+                }
+                _ => merged.push(range),
+            }
+        }
+        Self { ranges: merged }
+    }
+}
+
+impl From<RangeInclusive<u32>> for IntervalSet {
+    fn from(range: RangeInclusive<u32>) -> Self {
+        iter::once(range).collect()
+    }
+}
+
+/// Parses comma-separated ranges like `2-4,6-8`.
+impl FromStr for IntervalSet {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = vec![];
+        let mut rest = s;
+        loop {
+            let (range, after) = parse_range(s, rest)?;
+            ranges.push(range);
+            match after.strip_prefix(',') {
+                Some(next) => rest = next,
+                None if after.is_empty() => return Ok(ranges.into_iter().collect()),
+                None => return Err(ParseError::at(4, s, after, "`,` or the end of the input")),
+            }
+        }
+    }
+}
+
+/// Every assigned range, from both elves of every pair.
+fn claims(assignments: &[Assignments]) -> impl Iterator<Item = RangeInclusive<u32>> + '_ {
+    assignments
+        .iter()
+        .flat_map(|(left, right)| [left.clone(), right.clone()])
+}
+
+/// The sections assigned to at least `k` elves.
+pub fn claimed_by_at_least(assignments: &[Assignments], k: usize) -> IntervalSet {
+    IntervalSet::covered_at_least(claims(assignments), k)
+}
+
+/// The sections no elf is assigned to, between the lowest and the highest assigned ones.
+pub fn unclaimed(assignments: &[Assignments]) -> IntervalSet {
+    let claimed = claims(assignments).collect::<IntervalSet>();
+    match (claimed.ranges().first(), claimed.ranges().last()) {
+        (Some(first), Some(last)) => {
+            IntervalSet::from(*first.start()..=*last.end()).difference(&claimed)
+        }
+        _ => IntervalSet::new(),
+    }
+}
+
+/// Parses a pair of assignments like `2-4,6-8` per line, with sections of any integer type.
+pub fn parse_assignments<T: FromStr>(input: &str) -> Result<Vec<Assignments<T>>, ParseError> {
     input
         .lines()
         .map(|line| {
-            let (left, right) = line.split_once(',').unwrap();
-            (parse_range(left), parse_range(right))
+            let (left, rest) = parse_range(input, line)?;
+            let rest = rest
+                .strip_prefix(',')
+                .ok_or_else(|| ParseError::at(4, input, rest, "`,`"))?;
+            let (right, rest) = parse_range(input, rest)?;
+            if !rest.is_empty() {
+                return Err(ParseError::at(4, input, rest, "the end of the line"));
+            }
+            Ok((left, right))
         })
         .collect()
 }
 
-#[aoc(day4, part1)]
-fn part1(input: &[(RangeInclusive<u32>, RangeInclusive<u32>)]) -> usize {
-    input
+#[aoc_generator(day4)]
+fn generator(input: &str) -> Result<Vec<Assignments>, ParseError> {
+    parse_assignments(input)
+}
+
+/// How many pairs have one range fully overlapping the other.
+pub fn count_fully_overlapping<R: RangeOverlap>(pairs: &[(R, R)]) -> usize {
+    pairs
         .iter()
         .filter(|(left, right)| left.fully_overlaps(right) || right.fully_overlaps(left))
         .count()
 }
 
+/// How many pairs have overlapping ranges.
+pub fn count_overlapping<R: RangeOverlap>(pairs: &[(R, R)]) -> usize {
+    pairs
+        .iter()
+        .filter(|(left, right)| left.overlaps(right))
+        .count()
+}
+
+#[aoc(day4, part1)]
+fn part1(input: &[Assignments]) -> usize {
+    count_fully_overlapping(input)
+}
+
 #[aoc(day4, part2)]
-fn part2(input: &[(RangeInclusive<u32>, RangeInclusive<u32>)]) -> usize {
+fn part2(input: &[Assignments]) -> usize {
+    count_overlapping(input)
+}
+
+#[aoc(day4, part1, IntervalSet)]
+fn part1_interval_set(input: &[Assignments]) -> usize {
     input
         .iter()
-        .filter(|(left, right)| left.overlaps(right))
+        .map(|(left, right)| {
+            (
+                IntervalSet::from(left.clone()),
+                IntervalSet::from(right.clone()),
+            )
+        })
+        .filter(|(left, right)| {
+            let union = left.union(right);
+            union == *left || union == *right
+        })
         .count()
-}
\ No newline at end of file
+}
+
+#[aoc(day4, part2, IntervalSet)]
+fn part2_interval_set(input: &[Assignments]) -> usize {
+    input
+        .iter()
+        .map(|(left, right)| {
+            (
+                IntervalSet::from(left.clone()),
+                IntervalSet::from(right.clone()),
+            )
+        })
+        .filter(|(left, right)| !left.intersection(right).is_empty())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example() {
+        let pairs =
+            parse_assignments("2-4,6-8\n2-3,4-5\n5-7,7-9\n2-8,3-7\n6-6,4-6\n2-6,4-8").unwrap();
+        assert_eq!(part1(&pairs), 2);
+        assert_eq!(part2(&pairs), 4);
+        assert_eq!(part1_interval_set(&pairs), 2);
+        assert_eq!(part2_interval_set(&pairs), 4);
+    }
+
+    #[test]
+    fn signed_and_wide_sections() {
+        let pairs = parse_assignments::<i64>("-4--2,-3-5\n-9--7,-6-0").unwrap();
+        assert_eq!(pairs, [(-4..=-2, -3..=5), (-9..=-7, -6..=0)]);
+        assert_eq!(count_overlapping(&pairs), 1);
+        assert_eq!(count_fully_overlapping(&pairs), 0);
+
+        let big = 1 << 33;
+        let pairs =
+            parse_assignments::<u64>("8589934592-8589934600,8589934595-8589934595").unwrap();
+        assert_eq!(pairs, [(big..=big + 8, big + 3..=big + 3)]);
+        assert_eq!(count_fully_overlapping(&pairs), 1);
+        assert!(parse_assignments::<u32>("8589934592-8589934600,1-2").is_err());
+    }
+
+    #[test]
+    fn empty_and_reversed_ranges() {
+        let reversed = RangeInclusive::new(5, 3);
+        assert!(!reversed.overlaps(&(1..=9)) && !(1..=9).overlaps(&reversed));
+        assert!(!reversed.overlaps(&reversed));
+        assert!((1..=9).fully_overlaps(&reversed) && (7..=7).fully_overlaps(&reversed));
+        assert!(reversed.fully_overlaps(&reversed));
+        assert!(!reversed.fully_overlaps(&(4..=4)));
+
+        let empty = 4..4;
+        assert!(!empty.overlaps(&(0..9)) && !(0..9).overlaps(&empty));
+        assert!((6..7).fully_overlaps(&empty) && !empty.fully_overlaps(&(4..5)));
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 5..3;
+        assert!(!reversed.overlaps(&(0..9)) && (8..9).fully_overlaps(&reversed));
+        assert!((2..5).overlaps(&(4..6)) && !(2..4).overlaps(&(4..6)));
+
+        let pairs = parse_assignments::<u32>("5-3,1-9\n1-9,5-3\n5-3,6-4").unwrap();
+        assert_eq!(count_fully_overlapping(&pairs), 3);
+        assert_eq!(count_overlapping(&pairs), 0);
+    }
+
+    fn set<const N: usize>(ranges: [RangeInclusive<u32>; N]) -> IntervalSet {
+        ranges.into_iter().collect()
+    }
+
+    #[test]
+    fn normalises() {
+        assert_eq!(
+            set([RangeInclusive::new(5, 3), 1..=2, 3..=4, 8..=9, 7..=7]).ranges(),
+            [1..=4, 7..=9]
+        );
+        assert_eq!(set([2..=8, 3..=4]).ranges(), [2..=8]);
+        assert!(set([RangeInclusive::new(5, 3)]).is_empty());
+        assert_eq!(
+            "9-7,1-2,3-3".parse::<IntervalSet>().unwrap().ranges(),
+            [1..=3]
+        );
+    }
+
+    #[test]
+    fn insert() {
+        let mut ranges = set([2..=3, 6..=7, 10..=12]);
+        ranges.insert(RangeInclusive::new(5, 3));
+        assert_eq!(ranges.ranges(), [2..=3, 6..=7, 10..=12]);
+        ranges.insert(0..=0);
+        assert_eq!(ranges.ranges(), [0..=0, 2..=3, 6..=7, 10..=12]);
+        ranges.insert(4..=5);
+        assert_eq!(ranges.ranges(), [0..=0, 2..=7, 10..=12]);
+        ranges.insert(8..=8);
+        assert_eq!(ranges.ranges(), [0..=0, 2..=8, 10..=12]);
+        ranges.insert(11..=u32::MAX);
+        assert_eq!(ranges.ranges(), [0..=0, 2..=8, 10..=u32::MAX]);
+        ranges.insert(1..=20);
+        assert_eq!(ranges.ranges(), [0..=u32::MAX]);
+        assert_eq!(ranges.len(), 1 << 32);
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = set([0..=4, 10..=20]);
+        let b = set([3..=12, 20..=u32::MAX]);
+        assert_eq!(a.union(&b).ranges(), [0..=u32::MAX]);
+        assert_eq!(a.intersection(&b).ranges(), [3..=4, 10..=12, 20..=20]);
+        assert_eq!(a.difference(&b).ranges(), [0..=2, 13..=19]);
+        assert_eq!(b.difference(&a).ranges(), [5..=9, 21..=u32::MAX]);
+        assert_eq!(a.len(), 16);
+        assert!(a.contains(0) && a.contains(20) && !a.contains(5) && !a.contains(u32::MAX));
+    }
+
+    #[test]
+    fn complement_edges() {
+        assert_eq!(IntervalSet::new().complement().ranges(), [0..=u32::MAX]);
+        assert!(set([0..=u32::MAX]).complement().is_empty());
+        assert_eq!(set([0..=3]).complement().ranges(), [4..=u32::MAX]);
+        assert_eq!(set([7..=u32::MAX]).complement().ranges(), [0..=6]);
+        assert_eq!(
+            set([0..=0, u32::MAX..=u32::MAX]).complement().ranges(),
+            [1..=u32::MAX - 1]
+        );
+        assert!(set([0..=9]).difference(&set([0..=u32::MAX])).is_empty());
+    }
+
+    #[test]
+    fn covered_at_least() {
+        let ranges = [1..=4, 5..=8, 2..=7, 3..=3, RangeInclusive::new(9, 6)];
+        let covered = |k| IntervalSet::covered_at_least(ranges.clone(), k);
+        assert_eq!(covered(0).ranges(), [0..=u32::MAX]);
+        // Touching ranges cover their cells once each, and merge.
+        assert_eq!(covered(1).ranges(), [1..=8]);
+        assert_eq!(covered(2).ranges(), [2..=7]);
+        assert_eq!(covered(3).ranges(), [3..=3]);
+        assert!(covered(4).is_empty());
+        let edge = IntervalSet::covered_at_least([5..=u32::MAX, 0..=10, 7..=u32::MAX], 2);
+        assert_eq!(edge.ranges(), [5..=u32::MAX]);
+    }
+
+    #[test]
+    fn claims() {
+        let assignments = parse_assignments("2-4,10-12\n20-22,3-5").unwrap();
+        assert_eq!(claimed_by_at_least(&assignments, 2).ranges(), [3..=4]);
+        assert_eq!(unclaimed(&assignments).ranges(), [6..=9, 13..=19]);
+        assert!(unclaimed(&[]).is_empty());
+    }
+}
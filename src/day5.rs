@@ -1,102 +1,528 @@
-use std::str::FromStr;
+use crate::{utils::split_number, ParseError};
+use itertools::Itertools;
+use std::{error::Error, fmt, slice};
 
-const EMPTY_VEC: Vec<char> = Vec::new();
-
-#[derive(Debug)]
-struct Instruction {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
     amount: usize,
     source: usize,
     dest: usize,
 }
 
-impl FromStr for Instruction {
-    type Err = ();
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut it = s.split_ascii_whitespace().flat_map(str::parse::<usize>);
-        let amount = it.next().unwrap();
-        let source = it.next().unwrap() - 1;
-        let dest = it.next().unwrap() - 1;
+impl Instruction {
+    /// Moves `amount` crates from the stack at index `source` to the one at index `dest`,
+    /// counting from 0 unlike the text format.
+    pub fn new(amount: usize, source: usize, dest: usize) -> Self {
+        Self {
+            amount,
+            source,
+            dest,
+        }
+    }
+    pub fn amount(&self) -> usize {
+        self.amount
+    }
+    pub fn source(&self) -> usize {
+        self.source
+    }
+    pub fn dest(&self) -> usize {
+        self.dest
+    }
+}
+
+/// Writes the instruction as a line like `move 1 from 2 to 1`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "move {} from {} to {}",
+            self.amount,
+            self.source + 1,
+            self.dest + 1
+        )
+    }
+}
+
+/// Parses a line like `move 1 from 2 to 1`.
+fn parse_instruction(input: &str, line: &str) -> Result<Instruction, ParseError> {
+    let err = |rest, expected| ParseError::at(5, input, rest, expected);
+    let rest = line
+        .strip_prefix("move ")
+        .ok_or_else(|| err(line, "`move `"))?;
+    let (amount, rest) =
+        split_number::<usize>(rest).ok_or_else(|| err(rest, "the amount of crates to move"))?;
+    let rest = rest
+        .strip_prefix(" from ")
+        .ok_or_else(|| err(rest, "` from `"))?;
+    let (source, rest) = split_number::<usize>(rest)
+        .and_then(|(n, rest)| Some((n.checked_sub(1)?, rest)))
+        .ok_or_else(|| err(rest, "a stack number starting from 1"))?;
+    let rest = rest
+        .strip_prefix(" to ")
+        .ok_or_else(|| err(rest, "` to `"))?;
+    let (dest, rest) = split_number::<usize>(rest)
+        .and_then(|(n, rest)| Some((n.checked_sub(1)?, rest)))
+        .ok_or_else(|| err(rest, "a stack number starting from 1"))?;
+    if !rest.is_empty() {
+        return Err(err(rest, "the end of the line"));
+    }
+
+    Ok(Instruction {
+        amount,
+        source,
+        dest,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingStack(usize);
+
+impl fmt::Display for MissingStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "there's no stack number {}", self.0 + 1)
+    }
+}
+
+impl Error for MissingStack {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stacks(Vec<Vec<char>>);
 
-        Ok(Instruction {
+impl Stacks {
+    pub fn with_len(len: usize) -> Self {
+        Self(vec![vec![]; len])
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// The crates of every stack, from the bottom up.
+    pub fn stacks(&self) -> &[Vec<char>] {
+        &self.0
+    }
+    pub fn tops(&self) -> String {
+        self.0.iter().flat_map(|stack| stack.last()).collect()
+    }
+    /// Returns two different stacks at once.
+    fn pair_mut(&mut self, a: usize, b: usize) -> (&mut Vec<char>, &mut Vec<char>) {
+        assert_ne!(a, b, "can't borrow stack {} twice", a + 1);
+        if a < b {
+            let (left, right) = self.0.split_at_mut(b);
+            (&mut left[a], &mut right[0])
+        } else {
+            let (left, right) = self.0.split_at_mut(a);
+            (&mut right[0], &mut left[b])
+        }
+    }
+    /// The state of the stacks after each instruction, moving crates with `crane`.
+    /// Stops after the first failing instruction.
+    pub fn playback<'i, C: Crane>(
+        &self,
+        instructions: &'i [Instruction],
+        crane: C,
+    ) -> Playback<'i, C> {
+        Playback {
+            stacks: self.clone(),
+            instructions: instructions.iter(),
+            crane,
+        }
+    }
+}
+
+impl From<Vec<Vec<char>>> for Stacks {
+    fn from(stacks: Vec<Vec<char>>) -> Self {
+        Self(stacks)
+    }
+}
+
+/// Draws the stacks like the puzzle does, from the top crates down to the stack labels.
+impl fmt::Display for Stacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let height = self.0.iter().map(Vec::len).max().unwrap_or(0);
+        for level in (0..height).rev() {
+            for (i, stack) in self.0.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                match stack.get(level) {
+                    Some(c) => write!(f, "[{c}]")?,
+                    None => write!(f, "   ")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        for i in 0..self.len() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:^3}", i + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays instructions one at a time, see `Stacks::playback`.
+#[derive(Debug, Clone)]
+pub struct Playback<'i, C> {
+    stacks: Stacks,
+    instructions: slice::Iter<'i, Instruction>,
+    crane: C,
+}
+
+impl<C: Crane> Iterator for Playback<'_, C> {
+    type Item = Result<Stacks, MissingStack>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let instruction = self.instructions.next()?;
+        if let Err(err) = self.crane.execute(instruction, &mut self.stacks) {
+            self.instructions = [].iter();
+            return Some(Err(err));
+        }
+        Some(Ok(self.stacks.clone()))
+    }
+}
+
+impl Instruction {
+    fn check(&self, stacks: &Stacks) -> Result<(), MissingStack> {
+        [self.source, self.dest]
+            .into_iter()
+            .find(|&i| i >= stacks.len())
+            .map_or(Ok(()), |i| Err(MissingStack(i)))
+    }
+}
+
+/// A model of crane, which decides in what order crates land on their new stack.
+pub trait Crane {
+    /// Moves the top `amount` crates of `source`, or all of them if there are fewer, onto `dest`.
+    fn move_crates(&self, amount: usize, source: &mut Vec<char>, dest: &mut Vec<char>);
+
+    fn execute(&self, instruction: &Instruction, stacks: &mut Stacks) -> Result<(), MissingStack> {
+        instruction.check(stacks)?;
+        let Instruction {
             amount,
             source,
             dest,
-        })
+        } = *instruction;
+        // Moving crates onto their own stack leaves it as it was, whatever the crane.
+        if source != dest {
+            let (source, dest) = stacks.pair_mut(source, dest);
+            self.move_crates(amount, source, dest);
+        }
+        Ok(())
     }
 }
 
+impl<C: Crane + ?Sized> Crane for &C {
+    fn move_crates(&self, amount: usize, source: &mut Vec<char>, dest: &mut Vec<char>) {
+        (**self).move_crates(amount, source, dest)
+    }
+}
+
+/// Moves crates one at a time, so they land in reverse order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn move_crates(&self, amount: usize, source: &mut Vec<char>, dest: &mut Vec<char>) {
+        let at = source.len().saturating_sub(amount);
+        dest.extend(source.drain(at..).rev());
+    }
+}
+
+/// Moves all the crates at once, so they keep their order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn move_crates(&self, amount: usize, source: &mut Vec<char>, dest: &mut Vec<char>) {
+        let at = source.len().saturating_sub(amount);
+        dest.extend(source.drain(at..));
+    }
+}
+
+/// Moves up to `k` crates at a time, each batch keeping its order. A capacity of 0 acts like 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchCrane(pub usize);
+
+impl Crane for BatchCrane {
+    fn move_crates(&self, amount: usize, source: &mut Vec<char>, dest: &mut Vec<char>) {
+        let mut left = amount.min(source.len());
+        while left > 0 {
+            let batch = left.min(self.0.max(1));
+            dest.extend(source.drain(source.len() - batch..));
+            left -= batch;
+        }
+    }
+}
+
+/// Executes every instruction with `crane`, stopping at the first failing one.
+pub fn run<C: Crane>(
+    crane: C,
+    stacks: &mut Stacks,
+    instructions: &[Instruction],
+) -> Result<(), MissingStack> {
+    instructions
+        .iter()
+        .try_for_each(|instruction| crane.execute(instruction, stacks))
+}
+
+/// Parses the crates drawing, whose last line labels the stacks from 1 onward.
+fn parse_drawing(input: &str, drawing: &str) -> Result<Stacks, ParseError> {
+    let mut lines = drawing.lines().rev();
+    let labels = lines.next().unwrap_or(drawing);
+    let stacks_count = labels.split_ascii_whitespace().count();
+    if stacks_count == 0 {
+        return Err(ParseError::at(5, input, labels, "the stack labels"));
+    }
+    for (i, label) in labels.split_ascii_whitespace().enumerate() {
+        if label.parse::<usize>() != Ok(i + 1) {
+            return Err(ParseError::at(
+                5,
+                input,
+                label,
+                format!("stack label {}", i + 1),
+            ));
+        }
+    }
+
+    let mut stacks = Stacks::with_len(stacks_count);
+    for line in lines {
+        for (i, chunk) in line.as_bytes().chunks(4).enumerate() {
+            match chunk {
+                blank if blank.iter().all(|&b| b == b' ') => (),
+                _ if i >= stacks_count => {
+                    return Err(ParseError::at_byte(
+                        5,
+                        input,
+                        line,
+                        i * 4,
+                        format!("a crate within the {stacks_count} labelled stacks"),
+                    ))
+                }
+                [b'[', c, b']'] | [b'[', c, b']', b' '] if c.is_ascii_alphabetic() => {
+                    stacks.0[i].push(*c as char)
+                }
+                _ => {
+                    return Err(ParseError::at_byte(
+                        5,
+                        input,
+                        line,
+                        i * 4,
+                        "a crate like `[A]` or a blank space",
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(stacks)
+}
+
+/// Writes stacks, listed from the bottom up, and instructions back in the puzzle's format.
+/// It only parses back the same if there's at least one stack and every crate is a letter.
+pub fn serialize(stacks: &[Vec<char>], instructions: &[Instruction]) -> String {
+    let mut text = Stacks(stacks.to_vec()).to_string();
+    // The empty line is there even without instructions, since the drawing ends with it.
+    text.push_str("\n\n");
+    text.push_str(&instructions.iter().map(Instruction::to_string).join("\n"));
+    text
+}
+
 #[aoc_generator(day5)]
-fn generator(input: &str) -> ([Vec<char>; 9], Vec<Instruction>) {
-    let (crates_stack, instructions) = input.split_once("\n\n").unwrap();
+fn generator(input: &str) -> Result<(Stacks, Vec<Instruction>), ParseError> {
+    let (drawing, instructions) = input.split_once("\n\n").ok_or_else(|| {
+        ParseError::at_offset(
+            5,
+            input,
+            input.len(),
+            "an empty line between the drawing and the instructions",
+        )
+    })?;
 
-    let crates_stack = crates_stack
-        .lines()
-        .rev()
-        .filter(|line| line.contains('['))
-        .flat_map(|line| {
-            line.as_bytes()
-                .chunks(4)
-                .enumerate()
-                .filter_map(|(i, chunk)| {
-                    chunk[1]
-                        .is_ascii_alphabetic()
-                        .then(|| (i, chunk[1] as char))
-                })
-        })
-        .fold([EMPTY_VEC; 9], |mut stacks, (i, crate_id)| {
-            stacks[i].push(crate_id);
-            stacks
-        });
+    let stacks = parse_drawing(input, drawing)?;
     let instructions = instructions
         .lines()
-        .flat_map(Instruction::from_str)
-        .collect();
+        .map(|line| parse_instruction(input, line))
+        .collect::<Result<_, _>>()?;
 
-    (crates_stack, instructions)
+    Ok((stacks, instructions))
 }
 
 #[aoc(day5, part1)]
-fn part1((stacks, instructions): &([Vec<char>; 9], Vec<Instruction>)) -> String {
+fn part1((stacks, instructions): &(Stacks, Vec<Instruction>)) -> Result<String, MissingStack> {
     let mut stacks = stacks.clone();
-    for Instruction {
-        amount,
-        source,
-        dest,
-    } in instructions.iter()
-    {
-        for _ in 0..*amount {
-            if let Some(c) = stacks[*source].pop() {
-                stacks[*dest].push(c);
-            }
-        }
-    }
+    run(CrateMover9000, &mut stacks, instructions)?;
 
-    stacks
-        .into_iter()
-        .flat_map(|mut stack| stack.pop())
-        .collect()
+    Ok(stacks.tops())
 }
 
 #[aoc(day5, part2)]
-fn part2((stacks, instructions): &([Vec<char>; 9], Vec<Instruction>)) -> String {
+fn part2((stacks, instructions): &(Stacks, Vec<Instruction>)) -> Result<String, MissingStack> {
     let mut stacks = stacks.clone();
-    for Instruction {
-        amount,
-        source,
-        dest,
-    } in instructions.iter()
-    {
-        let mut temp = vec![];
-        for _ in 0..*amount {
-            if let Some(c) = stacks[*source].pop() {
-                temp.push(c);
-            }
+    run(CrateMover9001, &mut stacks, instructions)?;
+
+    Ok(stacks.tops())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::XorShift;
+
+    const EXAMPLE: &str = "    [D]    
+[N] [C]    
+[Z] [M] [P]
+ 1   2   3 
+
+move 1 from 2 to 1
+move 3 from 1 to 3
+move 2 from 2 to 1
+move 1 from 1 to 2";
+
+    #[test]
+    fn example() {
+        let puzzle = generator(EXAMPLE).unwrap();
+        assert_eq!(part1(&puzzle).unwrap(), "CMZ");
+        assert_eq!(part2(&puzzle).unwrap(), "MCD");
+    }
+
+    /// Draws the stacks the way the puzzle does, padding every line to the full width.
+    fn drawing(lines: &[&str]) -> String {
+        lines
+            .iter()
+            .map(|line| format!("{line:11}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn playback() {
+        let (stacks, instructions) = generator(EXAMPLE).unwrap();
+        assert_eq!(stacks.to_string(), EXAMPLE.split("\n\n").next().unwrap());
+        let states = stacks
+            .playback(&instructions, CrateMover9000)
+            .map(|state| state.unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            [
+                drawing(&["[D]", "[N] [C]", "[Z] [M] [P]", " 1   2   3"]),
+                drawing(&[
+                    "        [Z]",
+                    "        [N]",
+                    "    [C] [D]",
+                    "    [M] [P]",
+                    " 1   2   3",
+                ]),
+                drawing(&[
+                    "        [Z]",
+                    "        [N]",
+                    "[M]     [D]",
+                    "[C]     [P]",
+                    " 1   2   3",
+                ]),
+                drawing(&[
+                    "        [Z]",
+                    "        [N]",
+                    "        [D]",
+                    "[C] [M] [P]",
+                    " 1   2   3",
+                ]),
+            ]
+        );
+
+        let last = stacks.playback(&instructions, CrateMover9001).last();
+        assert_eq!(last.unwrap().unwrap().tops(), "MCD");
+    }
+
+    #[test]
+    fn playback_stops_at_missing_stack() {
+        let (stacks, mut instructions) = generator(EXAMPLE).unwrap();
+        instructions.insert(1, Instruction::new(1, 0, 3));
+        let mut playback = stacks.playback(&instructions, CrateMover9000);
+        assert_eq!(playback.next().unwrap().unwrap().tops(), "DCP");
+        let err = playback.next().unwrap().unwrap_err();
+        assert_eq!(err, MissingStack(3));
+        assert_eq!(err.to_string(), "there's no stack number 4");
+        assert!(playback.next().is_none());
+    }
+
+    #[test]
+    fn batch_crane() {
+        let (stacks, instructions) = generator(EXAMPLE).unwrap();
+        let tops = |crane| {
+            let mut stacks = stacks.clone();
+            run(crane, &mut stacks, &instructions).unwrap();
+            stacks.tops()
+        };
+        assert_eq!(tops(BatchCrane(0)), "CMZ");
+        assert_eq!(tops(BatchCrane(1)), "CMZ");
+        assert_eq!(tops(BatchCrane(3)), "MCD");
+
+        for (k, moved) in [(1, "EDCBA"), (2, "DEBCA"), (3, "CDEAB"), (9, "ABCDE")] {
+            let mut source = "ABCDE".chars().collect();
+            let mut dest = vec!['X'];
+            BatchCrane(k).move_crates(7, &mut source, &mut dest);
+            assert!(source.is_empty());
+            assert_eq!(
+                dest.iter().collect::<String>(),
+                format!("X{moved}"),
+                "k = {k}"
+            );
         }
-        temp.into_iter().rev().for_each(|c| stacks[*dest].push(c));
+        let mut source = "ABCDE".chars().collect();
+        let mut dest = vec![];
+        BatchCrane(2).move_crates(3, &mut source, &mut dest);
+        assert_eq!((source, dest), (vec!['A', 'B'], vec!['D', 'E', 'C']));
+    }
+
+    #[test]
+    fn drawing_wider_than_labels() {
+        let err = generator("[A]     [B]\n 1 \n\n").unwrap_err();
+        assert_eq!((err.line(), err.column()), (1, 9));
+        assert_eq!(err.expected(), "a crate within the 1 labelled stacks");
     }
 
-    stacks
-        .into_iter()
-        .flat_map(|mut stack| stack.pop())
-        .collect()
+    #[test]
+    fn serialize_puzzle_input() {
+        let input = include_str!("../input/2022/day5.txt").trim_end();
+        let (stacks, instructions) = generator(input).unwrap();
+        assert_eq!(serialize(stacks.stacks(), &instructions), input);
+    }
+
+    #[test]
+    fn serialize_without_instructions() {
+        let stacks = vec![vec!['A'], vec!['B', 'C']];
+        let text = serialize(&stacks, &[]);
+        assert_eq!(text, "    [C]\n[A] [B]\n 1   2 \n\n");
+        assert_eq!(generator(&text).unwrap(), (Stacks::from(stacks), vec![]));
+    }
+
+    /// Parses back random stacks and instructions, up to 12 stacks of up to 6 crates.
+    #[test]
+    fn parse_serialize_round_trip() {
+        let mut rng = XorShift::new(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..200 {
+            let len = rng.below(12) + 1;
+            let stacks = (0..len)
+                .map(|_| {
+                    (0..rng.below(7))
+                        .map(|_| (b'A' + rng.below(26) as u8) as char)
+                        .collect()
+                })
+                .collect::<Vec<Vec<char>>>();
+            let instructions = (0..rng.below(5))
+                .map(|_| Instruction::new(rng.below(10), rng.below(len), rng.below(len)))
+                .collect::<Vec<_>>();
+            let text = serialize(&stacks, &instructions);
+            let (parsed_stacks, parsed_instructions) = generator(&text).unwrap();
+            assert_eq!(parsed_stacks.stacks(), stacks, "{text}");
+            assert_eq!(parsed_instructions, instructions, "{text}");
+            assert_eq!(
+                serialize(parsed_stacks.stacks(), &parsed_instructions),
+                text
+            );
+        }
+    }
 }
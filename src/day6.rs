@@ -1,37 +1,280 @@
-trait AllUniqueIter: Iterator {
-    fn all_unique(self) -> bool;
-}
-
-impl<'a, I: Iterator<Item = &'a u8>> AllUniqueIter for I {
-    fn all_unique(self) -> bool {
-        let mut set = 0_u32;
-        for byte in self {
-            let offset = (byte - b'a') as usize;
-            if set & (1 << offset) != 0 {
-                return false;
-            } else {
-                set |= 1 << offset;
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, Read},
+};
+
+/// How many times each byte appears in a window, and how many bytes appear more than once.
+#[derive(Debug, Clone)]
+struct ByteCounts {
+    counts: [u32; 256],
+    duplicates: usize,
+}
+
+impl ByteCounts {
+    fn new() -> Self {
+        Self {
+            counts: [0; 256],
+            duplicates: 0,
+        }
+    }
+    fn push(&mut self, byte: u8) {
+        let count = &mut self.counts[byte as usize];
+        *count += 1;
+        if *count == 2 {
+            self.duplicates += 1;
+        }
+    }
+    fn pop(&mut self, byte: u8) {
+        let count = &mut self.counts[byte as usize];
+        *count -= 1;
+        if *count == 1 {
+            self.duplicates -= 1;
+        }
+    }
+    fn all_unique(&self) -> bool {
+        self.duplicates == 0
+    }
+}
+
+/// Every marker in a buffer, see `markers`.
+#[derive(Debug, Clone)]
+pub struct Markers<'i> {
+    input: &'i [u8],
+    window: usize,
+    counts: ByteCounts,
+    pos: usize,
+}
+
+impl Iterator for Markers<'_> {
+    type Item = usize;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.window == 0 {
+            return None;
+        }
+        while self.pos < self.input.len() {
+            self.counts.push(self.input[self.pos]);
+            if self.pos >= self.window {
+                self.counts.pop(self.input[self.pos - self.window]);
+            }
+            self.pos += 1;
+            if self.pos >= self.window && self.counts.all_unique() {
+                return Some(self.pos);
             }
         }
+        None
+    }
+}
 
-        true
+/// The end of every run of `window` distinct bytes, i.e. how many bytes
+/// have been read when each marker is complete. Overlapping markers are all reported.
+/// A `window` of 0 has no markers.
+pub fn markers(input: &[u8], window: usize) -> Markers<'_> {
+    Markers {
+        input,
+        window,
+        counts: ByteCounts::new(),
+        pos: 0,
+    }
+}
+
+/// The end of the first run of `window` distinct bytes.
+pub fn find_marker(input: &[u8], window: usize) -> Option<usize> {
+    markers(input, window).next()
+}
+
+/// The length of a start-of-packet marker.
+pub const PACKET: usize = 4;
+/// The length of a start-of-message marker.
+pub const MESSAGE: usize = 14;
+
+/// Finds markers in a stream fed a chunk at a time, remembering only the last `window` bytes.
+/// A `window` of 0 never completes a marker.
+#[derive(Debug, Clone)]
+pub struct MarkerDetector {
+    window: usize,
+    recent: VecDeque<u8>,
+    counts: ByteCounts,
+    bytes_read: usize,
+}
+
+impl MarkerDetector {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            recent: VecDeque::with_capacity(window),
+            counts: ByteCounts::new(),
+            bytes_read: 0,
+        }
+    }
+    pub fn window(&self) -> usize {
+        self.window
+    }
+    pub fn bytes_read(&self) -> usize {
+        self.bytes_read
+    }
+    /// Reads one more byte, and returns how many bytes have been read if it completes a marker.
+    pub fn push(&mut self, byte: u8) -> Option<usize> {
+        self.bytes_read += 1;
+        self.counts.push(byte);
+        self.recent.push_back(byte);
+        if self.recent.len() > self.window {
+            let oldest = self.recent.pop_front().unwrap();
+            self.counts.pop(oldest);
+        }
+        let complete = self.window > 0 && self.recent.len() == self.window;
+        (complete && self.counts.all_unique()).then_some(self.bytes_read)
+    }
+    /// The markers completed by `chunk`, found lazily as the iterator is consumed.
+    pub fn feed<'c>(&'c mut self, chunk: &'c [u8]) -> impl Iterator<Item = usize> + 'c {
+        chunk.iter().filter_map(|byte| self.push(*byte))
+    }
+}
+
+/// Feeds everything `reader` yields to each of the `detectors`, calling `on_marker`
+/// with the detector's index and the marker's position as soon as one is found.
+/// Returns how many bytes were read.
+pub fn scan<R, F>(
+    mut reader: R,
+    detectors: &mut [MarkerDetector],
+    mut on_marker: F,
+) -> io::Result<usize>
+where
+    R: Read,
+    F: FnMut(usize, usize),
+{
+    let mut buffer = [0; 8192];
+    let mut bytes_read = 0;
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(bytes_read),
+            Ok(len) => len,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        bytes_read += len;
+        for (i, detector) in detectors.iter_mut().enumerate() {
+            for marker in detector.feed(&buffer[..len]) {
+                on_marker(i, marker);
+            }
+        }
     }
 }
 
 #[aoc(day6, part1)]
-fn part1(input: &[u8]) -> usize {
-    input
-        .windows(4)
-        .enumerate()
-        .find_map(|(offset, bytes)| bytes.iter().all_unique().then_some(offset + 4))
-        .unwrap()
+fn part1(input: &[u8]) -> Option<usize> {
+    find_marker(input, PACKET)
 }
 
 #[aoc(day6, part2)]
-fn part2(input: &[u8]) -> usize {
-    input
-        .windows(14)
-        .enumerate()
-        .find_map(|(offset, bytes)| bytes.iter().all_unique().then_some(offset + 14))
-        .unwrap()
+fn part2(input: &[u8]) -> Option<usize> {
+    find_marker(input, MESSAGE)
+}
+
+#[aoc(day6, part1, Stream)]
+fn part1_stream(input: &[u8]) -> Option<usize> {
+    let mut first = None;
+    scan(input, &mut [MarkerDetector::new(PACKET)], |_, marker| {
+        first.get_or_insert(marker);
+    })
+    .ok()?;
+    first
+}
+
+#[aoc(day6, part2, Stream)]
+fn part2_stream(input: &[u8]) -> Option<usize> {
+    let mut first = None;
+    scan(input, &mut [MarkerDetector::new(MESSAGE)], |_, marker| {
+        first.get_or_insert(marker);
+    })
+    .ok()?;
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::XorShift;
+
+    /// Rescans every window, as a reference for the sliding count.
+    fn naive_markers(input: &[u8], window: usize) -> Vec<usize> {
+        if window == 0 {
+            return Vec::new();
+        }
+        input
+            .windows(window)
+            .enumerate()
+            .filter(|(_, w)| (1..w.len()).all(|i| !w[..i].contains(&w[i])))
+            .map(|(i, _)| i + window)
+            .collect()
+    }
+
+    #[test]
+    fn examples() {
+        for (input, packet, message) in [
+            ("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 7, 19),
+            ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+            ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+            ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+            ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+        ] {
+            assert_eq!(part1(input.as_bytes()), Some(packet), "{input}");
+            assert_eq!(part2(input.as_bytes()), Some(message), "{input}");
+        }
+        assert_eq!(find_marker(b"aaaaaaa", PACKET), None);
+        assert_eq!(find_marker(b"abc", PACKET), None);
+    }
+
+    #[test]
+    fn zero_window() {
+        assert_eq!(find_marker(b"abcd", 0), None);
+        assert_eq!(markers(b"abcd", 0).count(), 0);
+        let mut detector = MarkerDetector::new(0);
+        assert_eq!(detector.feed(b"abcd").count(), 0);
+        assert_eq!(detector.bytes_read(), 4);
+    }
+
+    #[test]
+    fn markers_match_rescanning() {
+        let mut rng = XorShift::new(0x0bad_5eed_1234_5678);
+        for _ in 0..300 {
+            let alphabet = [b'a', b'z', b'{', b'`', 0, 0xff, b'\n'];
+            let input: Vec<u8> = (0..rng.below(60))
+                .map(|_| alphabet[rng.below(alphabet.len())])
+                .collect();
+            let window = rng.below(6);
+            let expected = naive_markers(&input, window);
+            assert_eq!(markers(&input, window).collect::<Vec<_>>(), expected);
+            assert_eq!(find_marker(&input, window), expected.first().copied());
+        }
+    }
+
+    #[test]
+    fn chunked_detector_matches_rescanning() {
+        let mut rng = XorShift::new(0x5eed_cafe_f00d_0042);
+        for _ in 0..300 {
+            let alphabet = [b'a', b'b', b'c', b'z', b'{', 0, 0xff, b'\n'];
+            let input: Vec<u8> = (0..rng.below(80))
+                .map(|_| alphabet[rng.below(alphabet.len())])
+                .collect();
+            let window = rng.below(6);
+            let mut detector = MarkerDetector::new(window);
+            let mut found = Vec::new();
+            let mut rest = &input[..];
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at(1 + rng.below(rest.len()));
+                found.extend(detector.feed(chunk));
+                rest = tail;
+            }
+            assert_eq!(found, naive_markers(&input, window));
+            assert_eq!(detector.bytes_read(), input.len());
+
+            let mut scanned = Vec::new();
+            let detectors = &mut [MarkerDetector::new(window), MarkerDetector::new(PACKET)];
+            let read = scan(&input[..], detectors, |i, marker| scanned.push((i, marker)));
+            assert_eq!(read.unwrap(), input.len());
+            let only = |i| scanned.iter().filter(move |m| m.0 == i).map(|m| m.1);
+            assert_eq!(only(0).collect::<Vec<_>>(), found);
+            assert_eq!(only(1).collect::<Vec<_>>(), naive_markers(&input, PACKET));
+        }
+    }
 }
@@ -1,158 +1,303 @@
+use crate::ParseError;
 use itertools::Either;
 use nom::{
     branch::alt,
-    bytes::streaming::tag,
-    character::complete::{alpha1, char, digit1},
-    combinator::{map, map_res, opt, recognize},
-    multi::many1,
-    sequence::{pair, preceded, separated_pair},
+    bytes::complete::{tag, take_till1},
+    character::complete::{char, digit1},
+    combinator::{all_consuming, map, map_res, value},
+    sequence::{preceded, separated_pair},
     Finish, IResult,
 };
+use std::collections::BTreeMap;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum Command<'i> {
     Ls,
+    CdRoot,
     CdBack,
     CdInto(&'i str),
 }
 
 #[derive(Debug)]
-struct File {
+struct File<'i> {
+    name: &'i str,
     size: usize,
 }
 
-fn parse_file_or_command(input: &str) -> IResult<&str, Either<File, Command>> {
-    alt((
-        map(parse_file, Either::Left),
+#[derive(Debug)]
+enum Output<'i> {
+    Dir(&'i str),
+    File(File<'i>),
+}
+
+fn parse_line(input: &str) -> IResult<&str, Either<Output<'_>, Command<'_>>> {
+    all_consuming(alt((
+        map(parse_output, Either::Left),
         map(parse_command, Either::Right),
-    ))(input)
+    )))(input)
 }
 
-fn parse_command(input: &str) -> IResult<&str, Command> {
-    alt((parse_ls, parse_cd))(input)
+fn parse_output(input: &str) -> IResult<&str, Output<'_>> {
+    alt((map(parse_dir, Output::Dir), map(parse_file, Output::File)))(input)
 }
 
-fn parse_ls(input: &str) -> IResult<&str, Command> {
-    preceded(tag("$ "), tag("ls"))(input).map(|(rem, _)| (rem, Command::Ls))
+fn parse_command(input: &str) -> IResult<&str, Command<'_>> {
+    preceded(tag("$ "), alt((parse_ls, parse_cd)))(input)
 }
 
-fn parse_cd(input: &str) -> IResult<&str, Command> {
-    map_res(
-        separated_pair(
-            preceded(tag("$ "), tag("cd")),
-            char(' '),
-            alt((tag("/"), tag(".."), recognize(many1(alpha1)))),
-        ),
-        |(_cmd, arg)| {
-            Ok::<Command<'_>, ()>(
-                if arg == ".." {
-                    Command::CdBack
-                } else {
-                    Command::CdInto(arg)
-                }
-            )
-        },
+fn parse_ls(input: &str) -> IResult<&str, Command<'_>> {
+    value(Command::Ls, tag("ls"))(input)
+}
+
+fn parse_cd(input: &str) -> IResult<&str, Command<'_>> {
+    preceded(
+        tag("cd "),
+        alt((
+            value(Command::CdRoot, tag("/")),
+            map(parse_name, |arg| match arg {
+                ".." => Command::CdBack,
+                name => Command::CdInto(name),
+            }),
+        )),
     )(input)
 }
 
-fn parse_file(input: &str) -> IResult<&str, File> {
-    map_res(
-        separated_pair(parse_file_size, char(' '), parse_file_name),
-        |(size, _name)| Ok::<File, ()>(File { size }),
+fn parse_dir(input: &str) -> IResult<&str, &str> {
+    preceded(tag("dir "), parse_name)(input)
+}
+
+fn parse_file(input: &str) -> IResult<&str, File<'_>> {
+    map(
+        separated_pair(parse_file_size, char(' '), parse_name),
+        |(size, name)| File { name, size },
     )(input)
 }
 
 fn parse_file_size(input: &str) -> IResult<&str, usize> {
-    map_res(recognize(digit1), |size: &str| size.parse::<usize>())(input)
+    map_res(digit1, str::parse::<usize>)(input)
 }
 
-fn parse_file_name(input: &str) -> IResult<&str, &str> {
-    recognize(pair(alpha1, opt(pair(char('.'), alpha1))))(input)
+/// Any file or directory name, as long as it doesn't contain whitespace or `/`.
+fn parse_name(input: &str) -> IResult<&str, &str> {
+    take_till1(|c: char| c.is_whitespace() || c == '/')(input)
 }
 
-#[aoc(day7, part1)]
-fn part1(input: &str) -> usize {
-    let (mut all_dirs, mut stack) = input
-        .lines()
-        .filter(|line| !line.starts_with("dir"))
-        .filter_map(|line| {
-            parse_file_or_command(line)
-                .finish()
-                .map(|(_, file_cmd)| file_cmd)
-                .ok()
-        })
-        .fold(
-            (vec![], vec![]),
-            |(mut all_dirs, mut stack), file_or_cmd| match file_or_cmd {
-                Either::Left(file) => {
-                    *stack.last_mut().unwrap() += file.size;
-                    (all_dirs, stack)
-                }
-                Either::Right(Command::CdInto(_)) => {
-                    stack.push(0);
-                    (all_dirs, stack)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DirId(usize);
+
+#[derive(Debug, Clone)]
+pub struct Dir {
+    name: String,
+    parent: Option<DirId>,
+    dirs: BTreeMap<String, DirId>,
+    files: BTreeMap<String, usize>,
+    total_size: usize,
+}
+
+impl Dir {
+    fn new(name: &str, parent: Option<DirId>) -> Self {
+        Self {
+            name: name.to_owned(),
+            parent,
+            dirs: BTreeMap::new(),
+            files: BTreeMap::new(),
+            total_size: 0,
+        }
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn parent(&self) -> Option<DirId> {
+        self.parent
+    }
+    /// The size of every file in this directory and in its subdirectories.
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+    pub fn dirs(&self) -> impl Iterator<Item = (&str, DirId)> + '_ {
+        self.dirs.iter().map(|(name, id)| (name.as_str(), *id))
+    }
+    pub fn files(&self) -> impl Iterator<Item = (&str, usize)> + '_ {
+        self.files.iter().map(|(name, size)| (name.as_str(), *size))
+    }
+}
+
+/// The filesystem as explored by a terminal log of `cd`s and `ls`s.
+#[derive(Debug, Clone)]
+pub struct FsTree {
+    dirs: Vec<Dir>,
+}
+
+impl FsTree {
+    const ROOT: DirId = DirId(0);
+
+    pub fn from_log(input: &str) -> Result<Self, ParseError> {
+        let mut tree = Self {
+            dirs: vec![Dir::new("", None)],
+        };
+        let mut cwd = Self::ROOT;
+        for line in input.lines() {
+            let (_, output_or_cmd) =
+                parse_line(line)
+                    .finish()
+                    .map_err(|_| match line.strip_prefix("$ ") {
+                        Some(cmd) => ParseError::at(7, input, cmd, "`cd <name>` or `ls`"),
+                        None => ParseError::at(7, input, line, "`dir <name>` or `<size> <name>`"),
+                    })?;
+            match output_or_cmd {
+                Either::Left(Output::Dir(name)) => {
+                    tree.subdir_or_insert(cwd, name);
                 }
-                Either::Right(Command::CdBack) => {
-                    let curr_dir_size = stack.pop();
-                    *stack.last_mut().unwrap() += curr_dir_size.unwrap();
-                    all_dirs.push(curr_dir_size.unwrap());
-                    (all_dirs, stack)
+                Either::Left(Output::File(File { name, size })) => {
+                    tree.dirs[cwd.0].files.insert(name.to_owned(), size);
                 }
-                _ => (all_dirs, stack),
-            },
-        );
-    let root_size = stack.iter_mut().rev().fold(0, |prev, curr_size| {
-        *curr_size += prev;
-        *curr_size
-    });
-    all_dirs.push(root_size);
-    all_dirs
-        .into_iter()
-        .chain(stack.into_iter())
-        .filter(|x| *x <= 100_000)
+                Either::Right(Command::CdRoot) => cwd = Self::ROOT,
+                Either::Right(Command::CdInto(name)) => cwd = tree.subdir_or_insert(cwd, name),
+                Either::Right(Command::CdBack) => cwd = tree[cwd].parent.unwrap_or(Self::ROOT),
+                Either::Right(Command::Ls) => (),
+            }
+        }
+        tree.compute_total_sizes();
+
+        Ok(tree)
+    }
+    fn subdir_or_insert(&mut self, parent: DirId, name: &str) -> DirId {
+        if let Some(id) = self[parent].dirs.get(name) {
+            return *id;
+        }
+        let id = DirId(self.dirs.len());
+        self.dirs.push(Dir::new(name, Some(parent)));
+        self.dirs[parent.0].dirs.insert(name.to_owned(), id);
+        id
+    }
+    fn compute_total_sizes(&mut self) {
+        // Subdirectories are always created after their parent,
+        // so going backwards visits every child before its parent.
+        for i in (0..self.dirs.len()).rev() {
+            let dir = &mut self.dirs[i];
+            dir.total_size += dir.files.values().sum::<usize>();
+            if let Some(parent) = dir.parent {
+                let total_size = dir.total_size;
+                self.dirs[parent.0].total_size += total_size;
+            }
+        }
+    }
+    pub fn root(&self) -> DirId {
+        Self::ROOT
+    }
+    pub fn dirs(&self) -> impl Iterator<Item = (DirId, &Dir)> + '_ {
+        self.dirs.iter().enumerate().map(|(i, dir)| (DirId(i), dir))
+    }
+    /// Finds the directory at an absolute `path` like `/a/e`.
+    pub fn resolve(&self, path: &str) -> Option<DirId> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(Self::ROOT, |id, name| self[id].dirs.get(name).copied())
+    }
+    /// The absolute path of a directory, `/` for the root.
+    pub fn path(&self, id: DirId) -> String {
+        let mut names = vec![];
+        let mut curr = id;
+        while let Some(parent) = self[curr].parent {
+            names.push(self[curr].name());
+            curr = parent;
+        }
+        if names.is_empty() {
+            return "/".to_owned();
+        }
+        names
+            .into_iter()
+            .rev()
+            .fold(String::new(), |path, name| path + "/" + name)
+    }
+    /// The paths of every directory matching `predicate`.
+    pub fn find<P: Fn(&Dir) -> bool>(&self, predicate: P) -> Vec<String> {
+        self.dirs()
+            .filter(|(_, dir)| predicate(dir))
+            .map(|(id, _)| self.path(id))
+            .collect()
+    }
+}
+
+impl std::ops::Index<DirId> for FsTree {
+    type Output = Dir;
+    fn index(&self, id: DirId) -> &Self::Output {
+        &self.dirs[id.0]
+    }
+}
+
+#[aoc_generator(day7)]
+fn generator(input: &str) -> Result<FsTree, ParseError> {
+    FsTree::from_log(input)
+}
+
+#[aoc(day7, part1)]
+fn part1(input: &FsTree) -> usize {
+    input
+        .dirs()
+        .map(|(_, dir)| dir.total_size())
+        .filter(|size| *size <= 100_000)
         .sum()
 }
 
 #[aoc(day7, part2)]
-fn part2(input: &str) -> usize {
-    let (all_dirs, mut stack) = input
-        .lines()
-        .filter(|line| !line.starts_with("dir"))
-        .filter_map(|line| {
-            parse_file_or_command(line)
-                .finish()
-                .map(|(_, file_cmd)| file_cmd)
-                .ok()
-        })
-        .fold(
-            (vec![], vec![]),
-            |(mut all_dirs, mut stack), file_or_cmd| match file_or_cmd {
-                Either::Left(file) => {
-                    *stack.last_mut().unwrap() += file.size;
-                    (all_dirs, stack)
-                }
-                Either::Right(Command::CdInto(_)) => {
-                    stack.push(0);
-                    (all_dirs, stack)
-                }
-                Either::Right(Command::CdBack) => {
-                    let curr_dir_size = stack.pop();
-                    *stack.last_mut().unwrap() += curr_dir_size.unwrap();
-                    all_dirs.push(curr_dir_size.unwrap());
-                    (all_dirs, stack)
-                }
-                _ => (all_dirs, stack),
-            },
-        );
-    let root_size = stack.iter_mut().rev().fold(0, |prev, curr_size| {
-        *curr_size += prev;
-        *curr_size
-    });
-    let available_space = 70_000_000 - root_size;
-    all_dirs
-        .into_iter()
-        .chain(stack.into_iter())
-        .filter(|x| available_space + *x >= 30_000_000)
+fn part2(input: &FsTree) -> Option<usize> {
+    let available_space = 70_000_000_usize.saturating_sub(input[input.root()].total_size());
+    let needed_space = 30_000_000_usize.saturating_sub(available_space);
+    input
+        .dirs()
+        .map(|(_, dir)| dir.total_size())
+        .filter(|size| *size >= needed_space)
         .min()
-        .unwrap()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_cd_root() {
+        let log = "\
+$ cd /
+$ ls
+dir a-1.b
+14848514 b.txt
+$ cd a-1.b
+$ ls
+dir e2
+29116 f
+$ cd e2
+$ ls
+584 i.log
+$ cd /
+$ cd a-1.b
+$ ls
+2557 g-h.2
+$ cd ..
+$ cd ..
+$ ls
+8504156 c.dat";
+        let tree = FsTree::from_log(log).unwrap();
+        let e2 = tree.resolve("/a-1.b/e2").unwrap();
+        assert_eq!(tree.path(e2), "/a-1.b/e2");
+        assert_eq!(tree[e2].files().collect::<Vec<_>>(), [("i.log", 584)]);
+        let a = tree.resolve("/a-1.b").unwrap();
+        assert_eq!(tree[a].total_size(), 29116 + 584 + 2557);
+        assert_eq!(
+            tree[tree.root()].total_size(),
+            14848514 + 8504156 + 29116 + 584 + 2557
+        );
+        assert_eq!(tree.dirs().count(), 3);
+    }
+
+    #[test]
+    fn unparsable_lines() {
+        let err = FsTree::from_log("$ cd /\n$ cd /x/y").unwrap_err();
+        assert_eq!((err.line(), err.column()), (2, 3));
+        assert_eq!(err.expected(), "`cd <name>` or `ls`");
+        let err = FsTree::from_log("$ ls\ndir a/b").unwrap_err();
+        assert_eq!((err.line(), err.column()), (2, 1));
+        assert!(FsTree::from_log("$ ls\n12a b").is_err());
+        assert!(FsTree::from_log("$ rm -rf").is_err());
+    }
+}
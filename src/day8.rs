@@ -1,137 +1,271 @@
-use std::ops::Index;
+use crate::{
+    utils::grid::{Grid, Pos, CARDINALS},
+    ParseError,
+};
 
-use itertools::{Itertools, FoldWhile::{Continue, Done}};
+use itertools::FoldWhile::{Continue, Done};
+use itertools::Itertools;
+use std::{error::Error, fmt, ops::Deref};
 
 #[repr(transparent)]
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
-struct Tree(u8);
+pub struct Tree(u8);
 
-impl From<char> for Tree {
-    fn from(c: char) -> Self {
-        match c {
-            '0'..='9' => Self(c as u8 - b'0'),
-            _ => unreachable!(),
-        }
+impl Tree {
+    pub fn height(&self) -> u8 {
+        self.0
     }
 }
 
-#[derive(Debug)]
-struct Map {
-    width: usize,
-    height: usize,
-    trees: Vec<Vec<Tree>>,
+impl TryFrom<char> for Tree {
+    type Error = &'static str;
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        HeightAlphabet::Digits.tree(c)
+    }
 }
 
-impl Map {
-    pub fn new(trees: Vec<Vec<Tree>>) -> Self {
-        let width = trees[0].len();
-        let height = trees.len();
-        Self {
-            trees,
-            width,
-            height,
+/// The characters a map can use for tree heights.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HeightAlphabet {
+    /// `0` to `9`, like the puzzle.
+    #[default]
+    Digits,
+    /// `0` to `9`, then `a` to `z` for heights 10 to 35.
+    Extended,
+}
+
+impl HeightAlphabet {
+    pub fn tree(self, c: char) -> Result<Tree, &'static str> {
+        match (self, c) {
+            (_, '0'..='9') => Ok(Tree(c as u8 - b'0')),
+            (Self::Extended, 'a'..='z') => Ok(Tree(c as u8 - b'a' + 10)),
+            (Self::Digits, _) => Err("a tree height (0-9)"),
+            (Self::Extended, _) => Err("a tree height (0-9 or a-z)"),
         }
     }
-    pub fn contains_pos(&self, pos: (usize, usize)) -> bool {
-        let (x, y) = pos;
-        x < self.width && y < self.height
-    }
-    pub fn perimeter(&self) -> usize {
-        self.height * 2 + (self.width - 2) * 2
-    }
 }
 
-impl Index<&(usize, usize)> for Map {
-    type Output = Tree;
-    fn index(&self, index: &(usize, usize)) -> &Self::Output {
-        let (x, y) = *index;
-        &self.trees[y][x]
+/// A row of trees that isn't as wide as the first one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RaggedRow {
+    pub row: usize,
+    pub width: usize,
+    pub expected_width: usize,
+}
+
+impl fmt::Display for RaggedRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "row {} is {} trees wide, but the first one is {} trees wide",
+            self.row + 1,
+            self.width,
+            self.expected_width
+        )
     }
 }
 
-type Dir = (i32, i32);
+impl Error for RaggedRow {}
 
-const CARDINALS: [Dir; 4] = [(0, 1), (1, 0), (-1, 0), (0, -1)];
+/// A rectangular map of trees. It can be empty, or just a single row or column.
+#[derive(Debug, Clone)]
+pub struct Map(Grid<Tree>);
 
-#[derive(Debug)]
-struct Line {
-    dir: &'static Dir,
-    pos: (i32, i32),
+impl Map {
+    pub fn new(trees: Vec<Vec<Tree>>) -> Result<Self, RaggedRow> {
+        let expected_width = trees.first().map_or(0, Vec::len);
+        let widths = trees.iter().map(Vec::len).collect::<Vec<_>>();
+        Grid::from_rows(trees).map(Self).map_err(|row| RaggedRow {
+            row,
+            width: widths[row],
+            expected_width,
+        })
+    }
+    pub fn parse(input: &str, alphabet: HeightAlphabet) -> Result<Self, ParseError> {
+        Grid::parse(8, input, |_, c| alphabet.tree(c)).map(Self)
+    }
 }
 
-impl Line {
-    pub const fn new(pos: (usize, usize), dir: &'static Dir) -> Self {
-        Self {
-            pos: (pos.0 as i32, pos.1 as i32),
-            dir,
-        }
+impl Deref for Map {
+    type Target = Grid<Tree>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
 }
 
-impl Iterator for Line {
-    type Item = (usize, usize);
-    fn next(&mut self) -> Option<Self::Item> {
-        self.pos.0 += self.dir.0;
-        self.pos.1 += self.dir.1;
+/// What every tree can see, computed in linear time with a sweep of the map in each direction.
+#[derive(Debug, Clone)]
+struct Sightlines {
+    visible: Grid<bool>,
+    scenic_scores: Grid<usize>,
+}
 
-        self.pos
-            .0
-            .try_into()
-            .and_then(|x| self.pos.1.try_into().map(|y| (x, y)))
-            .ok()
+impl Sightlines {
+    fn new(map: &Map) -> Self {
+        let mut sightlines = Self {
+            visible: map.map(|_| false),
+            scenic_scores: map.map(|_| 1),
+        };
+        for row in 0..map.height() {
+            sightlines.sweep(map, (0..map.width()).map(|col| (row, col)));
+            sightlines.sweep(map, (0..map.width()).rev().map(|col| (row, col)));
+        }
+        for col in 0..map.width() {
+            sightlines.sweep(map, (0..map.height()).map(|row| (row, col)));
+            sightlines.sweep(map, (0..map.height()).rev().map(|row| (row, col)));
+        }
+        sightlines
+    }
+    /// Walks a line of trees starting from the edge, looking back towards it.
+    /// The stack keeps the trees that could still block the view of the next ones:
+    /// each is at least as tall as every tree between it and the current one.
+    fn sweep<I: Iterator<Item = Pos>>(&mut self, map: &Map, line: I) {
+        let mut stack: Vec<(usize, &Tree)> = vec![];
+        for (i, pos) in line.enumerate() {
+            let tree = &map[pos];
+            while stack.last().is_some_and(|(_, blocking)| *blocking < tree) {
+                stack.pop();
+            }
+            // Whatever is left on the stack is at least as tall as this tree.
+            let viewing_distance = stack.last().map_or(i, |(blocking_i, _)| i - blocking_i);
+            self.visible[pos] |= stack.is_empty();
+            self.scenic_scores[pos] *= viewing_distance;
+            stack.push((i, tree));
+        }
     }
 }
 
 #[aoc_generator(day8)]
-fn generator(input: &str) -> Map {
-    let trees = input
-        .lines()
-        .map(|line| line.chars().map(Into::into).collect::<Vec<Tree>>())
-        .collect();
-
-    Map::new(trees)
+fn generator(input: &str) -> Result<Map, ParseError> {
+    Map::parse(input, HeightAlphabet::Digits)
 }
 
 #[aoc(day8, part1)]
 fn part1(input: &Map) -> usize {
-    let trees_on_border = input.perimeter();
-    (1..input.width - 1)
-        .flat_map(|x| (1..input.height - 1).map(move |y| (x, y)))
+    Sightlines::new(input)
+        .visible
+        .iter()
+        .filter(|(_, visible)| **visible)
+        .count()
+}
+
+#[aoc(day8, part2)]
+fn part2(input: &Map) -> Option<usize> {
+    Sightlines::new(input)
+        .scenic_scores
+        .iter()
+        .map(|(_, score)| *score)
+        .max()
+}
+
+#[aoc(day8, part1, Rays)]
+fn part1_rays(input: &Map) -> usize {
+    input
+        .positions()
         .filter(|center| {
-            CARDINALS
-                .iter()
-                .any(|dir| {
-                    Line::new(*center, dir)
-                        .take_while(|pos| input.contains_pos(*pos))
-                        .map(|pos| &input[&pos])
-                        .all(|height| *height < input[center])
-                })
+            CARDINALS.iter().any(|dir| {
+                input
+                    .ray(*center, *dir)
+                    .map(|pos| &input[pos])
+                    .all(|height| *height < input[*center])
+            })
         })
         .count()
-        + trees_on_border
 }
 
-#[aoc(day8, part2)]
-fn part2(input: &Map) -> usize {
-    (0..input.width)
-        .flat_map(|x| (0..input.height).map(move |y| (x, y)))
+#[aoc(day8, part2, Rays)]
+fn part2_rays(input: &Map) -> Option<usize> {
+    input
+        .positions()
         .map(|center| {
             CARDINALS
                 .iter()
                 .map(|dir| {
-                    Line::new(center, dir)
+                    input
+                        .ray(center, *dir)
                         .fold_while(0, |visibility, pos| {
-                            if !input.contains_pos(pos) {
-                                Done(visibility)
-                            } else if input[&pos] < input[&center] {
+                            if input[pos] < input[center] {
                                 Continue(visibility + 1)
                             } else {
                                 Done(visibility + 1)
                             }
-                        }).into_inner()
+                        })
+                        .into_inner()
                 })
                 .product::<usize>()
         })
         .max()
-        .unwrap()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::XorShift;
+
+    #[test]
+    fn example() {
+        let map = generator("30373\n25512\n65332\n33549\n35390").unwrap();
+        assert_eq!((part1(&map), part2(&map)), (21, Some(8)));
+    }
+
+    #[test]
+    fn ragged_rows() {
+        let trees = |widths: &[usize]| widths.iter().map(|&w| vec![Tree(0); w]).collect();
+        let err = Map::new(trees(&[3, 3, 2, 1])).unwrap_err();
+        assert_eq!(
+            err,
+            RaggedRow {
+                row: 2,
+                width: 2,
+                expected_width: 3,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "row 3 is 2 trees wide, but the first one is 3 trees wide"
+        );
+        let err = Map::new(trees(&[2, 4])).unwrap_err();
+        assert_eq!((err.row, err.width, err.expected_width), (1, 4, 2));
+        let map = Map::new(trees(&[0, 0])).unwrap();
+        assert_eq!((map.height(), map.width()), (2, 0));
+    }
+
+    #[test]
+    fn extended_heights() {
+        let alphabet = HeightAlphabet::Extended;
+        let map = Map::parse("09a\nzb1", alphabet).unwrap();
+        let heights = map.positions().map(|pos| map[pos].height()).collect_vec();
+        assert_eq!(heights, [0, 9, 10, 35, 11, 1]);
+        // The example, with every height raised by 10.
+        let map = Map::parse("dadhd\ncffbc\ngfddc\nddfej\ndfdja", alphabet).unwrap();
+        assert_eq!((part1(&map), part2(&map)), (21, Some(8)));
+
+        let err = Map::parse("0a", HeightAlphabet::Digits).unwrap_err();
+        assert_eq!((err.column(), err.expected()), (2, "a tree height (0-9)"));
+        let err = Map::parse("aA", alphabet).unwrap_err();
+        assert_eq!(
+            (err.column(), err.expected()),
+            (2, "a tree height (0-9 or a-z)")
+        );
+    }
+
+    /// Checks the sweeps against the rays on random maps up to 6x6, including empty ones.
+    #[test]
+    fn sweeps_match_rays() {
+        let mut rng = XorShift::new(0x2545_f491_4f6c_dd1d);
+        for _ in 0..300 {
+            let (height, width) = (rng.below(7), rng.below(7));
+            let max_height = rng.below(10) + 1;
+            let trees = (0..height)
+                .map(|_| {
+                    (0..width)
+                        .map(|_| Tree(rng.below(max_height) as u8))
+                        .collect()
+                })
+                .collect();
+            let map = Map::new(trees).unwrap();
+            assert_eq!(part1(&map), part1_rays(&map), "{:?}", map);
+            assert_eq!(part2(&map), part2_rays(&map), "{:?}", map);
+        }
+    }
+}
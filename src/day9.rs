@@ -1,14 +1,19 @@
+use crate::ParseError;
 use itertools::Itertools;
-use std::{ops::AddAssign, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::{AddAssign, RangeInclusive},
+    str::FromStr,
+};
 
 #[derive(Debug, Default, Clone, Copy, Hash, Eq, PartialEq)]
-struct Pos {
-    x: i32,
-    y: i32,
+pub struct Pos {
+    pub x: i64,
+    pub y: i64,
 }
 
 impl Pos {
-    pub fn from_tuple(pos: (i32, i32)) -> Self {
+    pub fn from_tuple(pos: (i64, i64)) -> Self {
         Self { x: pos.0, y: pos.1 }
     }
 }
@@ -21,7 +26,7 @@ impl AddAssign for Pos {
 }
 
 #[derive(Debug, Clone, Copy)]
-enum Dir {
+pub enum Dir {
     Up,
     Down,
     Left,
@@ -36,7 +41,7 @@ impl FromStr for Dir {
             "D" => Ok(Self::Down),
             "L" => Ok(Self::Left),
             "R" => Ok(Self::Right),
-            _ => unreachable!(),
+            _ => Err(()),
         }
     }
 }
@@ -73,82 +78,446 @@ impl Movement for Pos {
 }
 
 #[aoc_generator(day9)]
-fn generator(input: &str) -> Vec<(Dir, usize)> {
+pub fn generator(input: &str) -> Result<Vec<(Dir, usize)>, ParseError> {
     input
         .lines()
         .map(|line| {
-            line.split_once(' ')
-                .map(|(left, right)| {
-                    (
-                        Dir::from_str(left).unwrap(),
-                        right.parse::<usize>().unwrap(),
-                    )
-                })
-                .unwrap()
+            let (dir, steps) = line
+                .split_once(' ')
+                .ok_or_else(|| ParseError::at(9, input, line, "a direction and a step count"))?;
+            let dir = Dir::from_str(dir)
+                .map_err(|_| ParseError::at(9, input, dir, "a direction (U, D, L or R)"))?;
+            let steps = steps
+                .parse::<usize>()
+                .map_err(|_| ParseError::at(9, input, steps, "a step count"))?;
+            Ok((dir, steps))
         })
         .collect()
 }
 
-#[repr(transparent)]
-#[derive(Debug)]
-struct Rope<const N: usize>([Pos; N]);
+/// How a knot moves to keep up with the knot ahead of it.
+///
+/// Where a knot goes may only depend on where its leader is relative to it: shifting
+/// both by the same offset has to shift the result by that offset too.
+/// `tail_segments` relies on it to skip the rest of a move once the whole rope shifts.
+pub trait FollowRule {
+    /// Where `knot` goes after its `leader` has moved.
+    fn follow(&self, knot: Pos, leader: Pos) -> Pos;
+}
+
+impl<R: FollowRule + ?Sized> FollowRule for Box<R> {
+    fn follow(&self, knot: Pos, leader: Pos) -> Pos {
+        (**self).follow(knot, leader)
+    }
+}
+
+/// The puzzle's rule: once the leader isn't touching anymore,
+/// the knot takes a step towards it, diagonally if needed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct KingFollow;
+
+impl FollowRule for KingFollow {
+    fn follow(&self, knot: Pos, leader: Pos) -> Pos {
+        MaxSlack(1).follow(knot, leader)
+    }
+}
+
+/// Like `KingFollow`, but the knot only ever steps horizontally or vertically,
+/// along the axis where it's furthest from its leader. Long ropes can stretch under this rule.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OrthogonalFollow;
+
+impl FollowRule for OrthogonalFollow {
+    fn follow(&self, mut knot: Pos, leader: Pos) -> Pos {
+        if knot.king_distance(&leader) > 1 {
+            let diff = knot.normal_diff(&leader);
+            if knot.x.abs_diff(leader.x) >= knot.y.abs_diff(leader.y) {
+                knot.x += diff.x;
+            } else {
+                knot.y += diff.y;
+            }
+        }
+        knot
+    }
+}
+
+/// Like `KingFollow`, but the knot lets its leader get up to this many cells away before moving.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxSlack(pub usize);
 
-impl<const N: usize> Default for Rope<N> {
-    fn default() -> Self {
-        Self([Pos::default(); N])
+impl FollowRule for MaxSlack {
+    fn follow(&self, mut knot: Pos, leader: Pos) -> Pos {
+        if knot.king_distance(&leader) > self.0 {
+            knot += knot.normal_diff(&leader);
+        }
+        knot
     }
 }
 
-impl<const N: usize> Rope<N> {
+#[derive(Debug, Clone)]
+pub struct Rope<R = KingFollow> {
+    knots: Vec<Pos>,
+    rule: R,
+}
+
+impl<R: FollowRule> Rope<R> {
+    /// A rope of `len` knots, at least the head, all starting at the origin.
+    pub fn new(len: usize, rule: R) -> Self {
+        Self {
+            knots: vec![Pos::default(); len.max(1)],
+            rule,
+        }
+    }
     #[inline(always)]
     pub fn head_mut(&mut self) -> &mut Pos {
-        &mut self.0[0]
+        &mut self.knots[0]
     }
     #[inline(always)]
     pub fn head(&self) -> &Pos {
-        &self.0[0]
+        &self.knots[0]
     }
     #[inline(always)]
     pub fn tail(&self) -> &Pos {
-        &self.0[N - 1]
+        &self.knots[self.knots.len() - 1]
+    }
+    pub fn knots(&self) -> &[Pos] {
+        &self.knots
     }
     pub fn m0ve(&mut self, dir: &Pos) {
         *self.head_mut() += *dir;
-        let mut prev_knot = *self.head();
-        for curr_knot in self.0[1..N].iter_mut() {
-            if prev_knot.king_distance(curr_knot) > 1 {
-                let diff = curr_knot.normal_diff(&prev_knot);
-                *curr_knot += diff;
-                prev_knot = *curr_knot;
-            } else {
-                break;
-            }
+        for i in 1..self.knots.len() {
+            self.knots[i] = self.rule.follow(self.knots[i], self.knots[i - 1]);
         }
     }
 }
 
-#[aoc(day9, part1)]
-fn part1(input: &[(Dir, usize)]) -> usize {
-    input
+/// Counts the cells visited by the tail of a rope of `len` knots.
+pub fn count_tail_positions<R: FollowRule>(moves: &[(Dir, usize)], len: usize, rule: R) -> usize {
+    moves
         .iter()
-        .flat_map(|(dir, steps)| std::iter::repeat(dir.to_pos()).take(*steps))
-        .scan(Rope::<2>::default(), |rope, dir| {
+        .flat_map(|(dir, steps)| std::iter::repeat_n(dir.to_pos(), *steps))
+        .scan(Rope::new(len, rule), |rope, dir| {
             rope.m0ve(&dir);
             Some(*rope.tail())
         })
+        .chain(std::iter::once(Pos::default()))
         .unique()
         .count()
 }
 
-#[aoc(day9, part2)]
-fn part2(input: &[(Dir, usize)]) -> usize {
-    input
+/// A straight run of cells, both ends included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Row { y: i64, xs: RangeInclusive<i64> },
+    Column { x: i64, ys: RangeInclusive<i64> },
+}
+
+impl Segment {
+    fn between(from: Pos, to: Pos) -> Self {
+        if from.y == to.y {
+            Self::Row {
+                y: from.y,
+                xs: from.x.min(to.x)..=from.x.max(to.x),
+            }
+        } else {
+            debug_assert_eq!(from.x, to.x, "segments are either horizontal or vertical");
+            Self::Column {
+                x: from.x,
+                ys: from.y.min(to.y)..=from.y.max(to.y),
+            }
+        }
+    }
+}
+
+/// The cells visited by the tail of a rope of `len` knots, as segments.
+///
+/// Each move is only simulated step by step until a step shifts the whole rope
+/// along with its head: from then on every step does the same,
+/// so the rest of the move is a straight segment for the tail.
+pub fn tail_segments<R: FollowRule>(moves: &[(Dir, usize)], len: usize, rule: R) -> Vec<Segment> {
+    let mut rope = Rope::new(len, rule);
+    let mut segments = vec![Segment::between(*rope.tail(), *rope.tail())];
+    let mut before = Vec::with_capacity(len);
+    for (dir, steps) in moves {
+        let dir = dir.to_pos();
+        for step in 1..=*steps {
+            before.clear();
+            before.extend_from_slice(rope.knots());
+            rope.m0ve(&dir);
+            segments.push(Segment::between(*rope.tail(), *rope.tail()));
+
+            let is_shifted = before
+                .iter()
+                .zip(rope.knots())
+                .all(|(before, after)| after.x - before.x == dir.x && after.y - before.y == dir.y);
+            if is_shifted {
+                let remaining = (steps - step) as i64;
+                let from = *rope.tail();
+                for knot in rope.knots.iter_mut() {
+                    *knot += Pos::from_tuple((dir.x * remaining, dir.y * remaining));
+                }
+                segments.push(Segment::between(from, *rope.tail()));
+                break;
+            }
+        }
+    }
+    segments
+}
+
+/// Merges overlapping ranges, sorting them in the process.
+fn merge_ranges(mut ranges: Vec<RangeInclusive<i64>>) -> Vec<RangeInclusive<i64>> {
+    ranges.sort_unstable_by_key(|range| *range.start());
+    let mut merged: Vec<RangeInclusive<i64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if *range.start() <= *last.end() + 1 => {
+                *last = *last.start()..=*last.end().max(range.end());
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Counts the distinct cells covered by `segments`.
+///
+/// Rows and columns are merged on their own, then the cells covered by both
+/// are found sweeping the columns from left to right, with a Fenwick tree
+/// counting the rows that are open at each column.
+pub fn count_cells(segments: &[Segment]) -> usize {
+    let mut rows: HashMap<i64, Vec<RangeInclusive<i64>>> = HashMap::new();
+    let mut cols: HashMap<i64, Vec<RangeInclusive<i64>>> = HashMap::new();
+    for segment in segments {
+        match segment {
+            Segment::Row { y, xs } => rows.entry(*y).or_default().push(xs.clone()),
+            Segment::Column { x, ys } => cols.entry(*x).or_default().push(ys.clone()),
+        }
+    }
+    let rows = rows
+        .into_iter()
+        .flat_map(|(y, xs)| merge_ranges(xs).into_iter().map(move |xs| (y, xs)))
+        .collect::<Vec<_>>();
+    let cols = cols
+        .into_iter()
+        .flat_map(|(x, ys)| merge_ranges(ys).into_iter().map(move |ys| (x, ys)))
+        .collect::<Vec<_>>();
+    let len = |range: &RangeInclusive<i64>| (range.end() - range.start() + 1) as usize;
+    let covered = rows.iter().map(|(_, xs)| len(xs)).sum::<usize>()
+        + cols.iter().map(|(_, ys)| len(ys)).sum::<usize>();
+
+    // Events at the same column are ordered opening/closing rows first, then querying.
+    enum Event {
+        Row(usize, i64),
+        Query(RangeInclusive<i64>),
+    }
+    let ys = rows
         .iter()
-        .flat_map(|(dir, steps)| std::iter::repeat(dir.to_pos()).take(*steps))
-        .scan(Rope::<10>::default(), |rope, dir| {
+        .map(|(y, _)| *y)
+        .sorted_unstable()
+        .dedup()
+        .collect::<Vec<_>>();
+    let mut events = rows
+        .iter()
+        .flat_map(|(y, xs)| {
+            let y_idx = ys.binary_search(y).unwrap_or_default();
+            [
+                (*xs.start(), Event::Row(y_idx, 1)),
+                (*xs.end() + 1, Event::Row(y_idx, -1)),
+            ]
+        })
+        .chain(cols.into_iter().map(|(x, ys)| (x, Event::Query(ys))))
+        .collect::<Vec<_>>();
+    events.sort_by_key(|(x, event)| (*x, matches!(event, Event::Query(_))));
+
+    let mut open_rows = FenwickTree::new(ys.len());
+    let mut overlapping = 0;
+    for (_, event) in events {
+        match event {
+            Event::Row(y_idx, delta) => open_rows.add(y_idx, delta),
+            Event::Query(range) => {
+                let from = ys.partition_point(|y| y < range.start());
+                let to = ys.partition_point(|y| y <= range.end());
+                overlapping += open_rows.prefix_sum(to) - open_rows.prefix_sum(from);
+            }
+        }
+    }
+
+    covered - overlapping as usize
+}
+
+struct FenwickTree(Vec<i64>);
+
+impl FenwickTree {
+    fn new(len: usize) -> Self {
+        Self(vec![0; len + 1])
+    }
+    fn add(&mut self, idx: usize, delta: i64) {
+        let mut i = idx + 1;
+        while i < self.0.len() {
+            self.0[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+    /// The sum of the first `len` values.
+    fn prefix_sum(&self, len: usize) -> i64 {
+        let mut i = len;
+        let mut sum = 0;
+        while i > 0 {
+            sum += self.0[i];
+            i -= i & i.wrapping_neg();
+        }
+        sum
+    }
+}
+
+/// The position of every knot after every step of a list of moves,
+/// starting with the rope all bunched up at the origin.
+#[derive(Debug, Clone)]
+pub struct RopeTrace {
+    frames: Vec<Vec<Pos>>,
+}
+
+impl RopeTrace {
+    pub fn record<R: FollowRule>(moves: &[(Dir, usize)], len: usize, rule: R) -> Self {
+        let mut rope = Rope::new(len, rule);
+        let mut frames = vec![rope.knots().to_vec()];
+        for dir in moves
+            .iter()
+            .flat_map(|(dir, steps)| std::iter::repeat_n(dir.to_pos(), *steps))
+        {
             rope.m0ve(&dir);
-            Some(*rope.tail())
+            frames.push(rope.knots().to_vec());
+        }
+        Self { frames }
+    }
+    /// The knots at each step, head first.
+    pub fn frames(&self) -> &[Vec<Pos>] {
+        &self.frames
+    }
+    fn tail_at(&self, step: usize) -> Option<Pos> {
+        self.frames
+            .get(step)
+            .and_then(|knots| knots.last().copied())
+    }
+    /// How many steps the tail spent on each cell it visited.
+    pub fn visit_counts(&self) -> HashMap<Pos, usize> {
+        let mut counts = HashMap::new();
+        for tail in (0..self.frames.len()).flat_map(|step| self.tail_at(step)) {
+            *counts.entry(tail).or_insert(0) += 1;
+        }
+        counts
+    }
+    /// The smallest area containing every knot at every step, as its top left and bottom right corners.
+    fn bounds(&self) -> (Pos, Pos) {
+        self.frames
+            .iter()
+            .flatten()
+            .fold((Pos::default(), Pos::default()), |(min, max), knot| {
+                (
+                    Pos::from_tuple((min.x.min(knot.x), min.y.min(knot.y))),
+                    Pos::from_tuple((max.x.max(knot.x), max.y.max(knot.y))),
+                )
+            })
+    }
+    fn render_with<F: Fn(Pos) -> char>(&self, cell: F) -> String {
+        let (min, max) = self.bounds();
+        let mut grid = String::new();
+        for y in min.y..=max.y {
+            grid.extend((min.x..=max.x).map(|x| cell(Pos::from_tuple((x, y)))));
+            grid.push('\n');
+        }
+        grid
+    }
+    /// Draws the rope after `step` steps like the puzzle does: `H` for the head,
+    /// then the knots numbered from 1 (`T` if the rope is only 2 knots long),
+    /// `s` for the start and `#` for the cells the tail has visited so far.
+    pub fn render(&self, step: usize) -> Option<String> {
+        let knots = self.frames.get(step)?;
+        let visited = (0..=step)
+            .flat_map(|step| self.tail_at(step))
+            .collect::<HashSet<_>>();
+        Some(
+            self.render_with(|pos| match knots.iter().position(|knot| *knot == pos) {
+                Some(0) => 'H',
+                Some(_) if knots.len() == 2 => 'T',
+                Some(i) => char::from_digit(i as u32 % 36, 36).unwrap_or('?'),
+                None if pos == Pos::default() => 's',
+                None if visited.contains(&pos) => '#',
+                None => '.',
+            }),
+        )
+    }
+    /// Draws how many steps the tail spent on each cell, `*` for 10 or more.
+    pub fn render_heatmap(&self) -> String {
+        let counts = self.visit_counts();
+        self.render_with(|pos| match counts.get(&pos) {
+            None => '.',
+            Some(count @ 1..=9) => char::from_digit(*count as u32, 10).unwrap_or('*'),
+            Some(_) => '*',
         })
-        .unique()
-        .count()
+    }
+}
+
+#[aoc(day9, part1)]
+fn part1(input: &[(Dir, usize)]) -> usize {
+    count_tail_positions(input, 2, KingFollow)
+}
+
+#[aoc(day9, part2)]
+fn part2(input: &[(Dir, usize)]) -> usize {
+    count_tail_positions(input, 10, KingFollow)
+}
+
+#[aoc(day9, part1, Segments)]
+fn part1_segments(input: &[(Dir, usize)]) -> usize {
+    count_cells(&tail_segments(input, 2, KingFollow))
+}
+
+#[aoc(day9, part2, Segments)]
+fn part2_segments(input: &[(Dir, usize)]) -> usize {
+    count_cells(&tail_segments(input, 10, KingFollow))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::XorShift;
+
+    #[test]
+    fn examples() {
+        let moves = generator("R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2").unwrap();
+        assert_eq!((part1(&moves), part2(&moves)), (13, 1));
+        let moves = generator("R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20").unwrap();
+        assert_eq!(part2_segments(&moves), 36);
+    }
+
+    /// Checks the segments against the step by step simulation on random moves.
+    #[test]
+    fn segments_match_steps() {
+        let mut rng = XorShift::new(0x5851_f42d_4c95_7f2d);
+        let dirs = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+        for _ in 0..300 {
+            let moves = (0..rng.below(30))
+                .map(|_| (dirs[rng.below(4)], rng.below(20) + 1))
+                .collect::<Vec<_>>();
+            let len = rng.below(12) + 1;
+            let count = |segments: Vec<Segment>| count_cells(&segments);
+            assert_eq!(
+                count(tail_segments(&moves, len, KingFollow)),
+                count_tail_positions(&moves, len, KingFollow),
+                "{len} knots, {moves:?}"
+            );
+            assert_eq!(
+                count(tail_segments(&moves, len, OrthogonalFollow)),
+                count_tail_positions(&moves, len, OrthogonalFollow),
+                "{len} knots, {moves:?}"
+            );
+            let slack = MaxSlack(rng.below(4) + 1);
+            assert_eq!(
+                count(tail_segments(&moves, len, slack)),
+                count_tail_positions(&moves, len, slack),
+                "{len} knots, {slack:?}, {moves:?}"
+            );
+        }
+    }
 }
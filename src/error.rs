@@ -0,0 +1,89 @@
+use std::{borrow::Cow, error::Error, fmt};
+
+/// A malformed puzzle input, pointing at the 1-based line and column where
+/// parsing stopped and describing what was expected there instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    day: u8,
+    line: usize,
+    column: usize,
+    expected: Cow<'static, str>,
+}
+
+impl ParseError {
+    pub fn new(
+        day: u8,
+        line: usize,
+        column: usize,
+        expected: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            day,
+            line,
+            column,
+            expected: expected.into(),
+        }
+    }
+    /// Builds an error located at the start of `rest`, which must be a subslice of `input`.
+    pub fn at(day: u8, input: &str, rest: &str, expected: impl Into<Cow<'static, str>>) -> Self {
+        Self::at_byte(day, input, rest, 0, expected)
+    }
+    /// Builds an error located `byte` bytes into `slice`, which must be a subslice of `input`.
+    pub fn at_byte(
+        day: u8,
+        input: &str,
+        slice: &str,
+        byte: usize,
+        expected: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        let offset = (slice.as_ptr() as usize).wrapping_sub(input.as_ptr() as usize);
+        debug_assert!(offset <= input.len(), "`slice` isn't a subslice of `input`");
+        Self::at_offset(day, input, offset.saturating_add(byte), expected)
+    }
+    /// Builds an error located at byte `offset` of `input`, clamped to its end.
+    pub fn at_offset(
+        day: u8,
+        input: &str,
+        offset: usize,
+        expected: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        let before = &input.as_bytes()[..offset.min(input.len())];
+        let line_start = before
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        // Count chars rather than bytes by skipping UTF-8 continuation bytes.
+        let column = before[line_start..]
+            .iter()
+            .filter(|&&b| (b as i8) >= -0x40)
+            .count()
+            + 1;
+
+        Self::new(day, line, column, expected)
+    }
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+    pub fn line(&self) -> usize {
+        self.line
+    }
+    pub fn column(&self) -> usize {
+        self.column
+    }
+    pub fn expected(&self) -> &str {
+        &self.expected
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "day {} input, line {}, column {}: expected {}",
+            self.day, self.line, self.column, self.expected
+        )
+    }
+}
+
+impl Error for ParseError {}
@@ -2,7 +2,8 @@
 extern crate aoc_runner_derive;
 extern crate aoc_runner;
 
-mod utils;
+mod error;
+pub mod utils;
 pub mod day1;
 pub mod day2;
 pub mod day3;
@@ -16,4 +17,6 @@ pub mod day10;
 pub mod day11;
 pub mod day12;
 
+pub use error::ParseError;
+
 aoc_lib! { year = 2022 }
@@ -1,5 +1,7 @@
+pub mod grid;
+
 use core::cmp::Reverse;
-use std::collections::BinaryHeap;
+use std::{collections::BinaryHeap, str::FromStr};
 
 pub trait CollectInto<T, E: Extend<T>> {
     fn collect_into(self, collector: &mut E) -> &mut E;
@@ -58,4 +60,33 @@ where
     I: Iterator<Item = T>,
     T: Ord,
 {
-}
\ No newline at end of file
+}
+
+/// Splits the leading (optionally negative) integer off `s`.
+pub fn split_number<T: FromStr>(s: &str) -> Option<(T, &str)> {
+    let sign_len = usize::from(s.starts_with('-'));
+    let digits_len = s[sign_len..].bytes().take_while(u8::is_ascii_digit).count();
+    if digits_len == 0 {
+        return None;
+    }
+    let (number, rest) = s.split_at(sign_len + digits_len);
+    number.parse().ok().map(|number| (number, rest))
+}
+
+/// A xorshift64 generator, so randomized tests check the same cases on every run.
+#[cfg(test)]
+pub struct XorShift(u64);
+
+#[cfg(test)]
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+    /// A number in `0..bound`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
@@ -0,0 +1,266 @@
+use crate::ParseError;
+use std::{
+    fmt,
+    iter::StepBy,
+    ops::{Index, IndexMut},
+    slice,
+};
+
+/// A `(row, col)` position on a grid.
+pub type Pos = (usize, usize);
+/// A `(row, col)` offset, like one step in some direction.
+pub type Dir = (isize, isize);
+
+pub const CARDINALS: [Dir; 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+pub const DIRECTIONS: [Dir; 8] = [
+    (-1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+];
+
+/// A rectangular grid, stored row by row.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    /// Builds a grid out of its rows, or returns the index of the first row
+    /// that isn't as long as the ones before it.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Self, usize> {
+        let width = rows.first().map_or(0, Vec::len);
+        if let Some(ragged) = rows.iter().position(|row| row.len() != width) {
+            return Err(ragged);
+        }
+        let height = rows.len();
+        let cells = rows.into_iter().flatten().collect();
+        Ok(Self {
+            width,
+            height,
+            cells,
+        })
+    }
+    /// Parses a grid with a character per cell and a line per row. `cell` gets
+    /// each character with its position, and returns what it expected on failure.
+    pub fn parse<F>(day: u8, input: &str, mut cell: F) -> Result<Self, ParseError>
+    where
+        F: FnMut(Pos, char) -> Result<T, &'static str>,
+    {
+        let width = input.lines().next().map_or(0, |line| line.chars().count());
+        let mut cells = Vec::with_capacity(input.len());
+        let mut height = 0;
+        for (row, line) in input.lines().enumerate() {
+            let mut chars = line.char_indices();
+            for (col, (byte, c)) in chars.by_ref().take(width).enumerate() {
+                cells.push(
+                    cell((row, col), c).map_err(|expected| {
+                        ParseError::at_byte(day, input, line, byte, expected)
+                    })?,
+                );
+            }
+            let row_end = chars.next().map(|(byte, _)| byte);
+            if row_end.is_some() || cells.len() < (row + 1) * width {
+                return Err(ParseError::at_byte(
+                    day,
+                    input,
+                    line,
+                    row_end.unwrap_or(line.len()),
+                    format!("a row of {width} cells"),
+                ));
+            }
+            height += 1;
+        }
+
+        Ok(Self {
+            width,
+            height,
+            cells,
+        })
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+    pub fn contains(&self, (row, col): Pos) -> bool {
+        row < self.height && col < self.width
+    }
+    pub fn get(&self, pos: Pos) -> Option<&T> {
+        self.contains(pos)
+            .then(|| &self.cells[pos.0 * self.width + pos.1])
+    }
+    pub fn get_mut(&mut self, pos: Pos) -> Option<&mut T> {
+        self.contains(pos)
+            .then(|| &mut self.cells[pos.0 * self.width + pos.1])
+    }
+    /// Every position, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = Pos> {
+        let width = self.width;
+        (0..self.height).flat_map(move |row| (0..width).map(move |col| (row, col)))
+    }
+    /// Every cell along with its position, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = (Pos, &T)> + '_ {
+        self.positions().zip(self.cells.iter())
+    }
+    /// The first position whose cell matches `predicate`.
+    pub fn position<P: FnMut(&T) -> bool>(&self, predicate: P) -> Option<Pos> {
+        self.cells
+            .iter()
+            .position(predicate)
+            .map(|i| (i / self.width, i % self.width))
+    }
+    pub fn row(&self, row: usize) -> &[T] {
+        assert!(row < self.height, "row {row} is out of bounds");
+        &self.cells[row * self.width..(row + 1) * self.width]
+    }
+    pub fn column(&self, col: usize) -> StepBy<slice::Iter<'_, T>> {
+        assert!(col < self.width, "column {col} is out of bounds");
+        self.cells[col..].iter().step_by(self.width.max(1))
+    }
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[T]> + '_ {
+        (0..self.height).map(move |row| self.row(row))
+    }
+    pub fn columns(&self) -> impl DoubleEndedIterator<Item = StepBy<slice::Iter<'_, T>>> + '_ {
+        (0..self.width).map(move |col| self.column(col))
+    }
+    /// The position one step away from `pos` in direction `dir`, if it's on the grid.
+    pub fn step(&self, (row, col): Pos, (d_row, d_col): Dir) -> Option<Pos> {
+        let pos = (
+            row.checked_add_signed(d_row)?,
+            col.checked_add_signed(d_col)?,
+        );
+        self.contains(pos).then_some(pos)
+    }
+    /// The up to 4 positions sharing a side with `pos`.
+    pub fn neighbours4(&self, pos: Pos) -> impl Iterator<Item = Pos> + '_ {
+        CARDINALS
+            .into_iter()
+            .filter_map(move |dir| self.step(pos, dir))
+    }
+    /// The up to 8 positions sharing a side or a corner with `pos`.
+    pub fn neighbours8(&self, pos: Pos) -> impl Iterator<Item = Pos> + '_ {
+        DIRECTIONS
+            .into_iter()
+            .filter_map(move |dir| self.step(pos, dir))
+    }
+    /// The positions met walking from `pos`, excluded, towards `dir` until the edge of the grid.
+    pub fn ray(&self, pos: Pos, dir: Dir) -> Ray {
+        Ray {
+            pos,
+            dir,
+            width: self.width,
+            height: self.height,
+        }
+    }
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> Grid<U> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.iter().map(f).collect(),
+        }
+    }
+    /// Draws the grid with a character per cell, given by `cell`.
+    pub fn render<F: FnMut(Pos, &T) -> char>(&self, mut cell: F) -> String {
+        let mut rendered = String::with_capacity((self.width + 1) * self.height);
+        for (pos, value) in self.iter() {
+            rendered.push(cell(pos, value));
+            if pos.1 + 1 == self.width {
+                rendered.push('\n');
+            }
+        }
+        rendered
+    }
+}
+
+impl<T> Index<Pos> for Grid<T> {
+    type Output = T;
+    fn index(&self, (row, col): Pos) -> &Self::Output {
+        assert!(col < self.width, "column {col} is out of bounds");
+        &self.cells[row * self.width + col]
+    }
+}
+
+impl<T> IndexMut<Pos> for Grid<T> {
+    fn index_mut(&mut self, (row, col): Pos) -> &mut Self::Output {
+        assert!(col < self.width, "column {col} is out of bounds");
+        &mut self.cells[row * self.width + col]
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Grid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.rows() {
+            for cell in row {
+                write!(f, "{cell}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Walks a grid in a straight line, see `Grid::ray`.
+#[derive(Debug, Clone)]
+pub struct Ray {
+    pos: Pos,
+    dir: Dir,
+    width: usize,
+    height: usize,
+}
+
+impl Iterator for Ray {
+    type Item = Pos;
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.pos.0.checked_add_signed(self.dir.0)?;
+        let col = self.pos.1.checked_add_signed(self.dir.1)?;
+        if row >= self.height || col >= self.width {
+            return None;
+        }
+        self.pos = (row, col);
+        Some(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> Grid<Pos> {
+        let rows = (0..3).map(|row| (0..3).map(|col| (row, col)).collect());
+        Grid::from_rows(rows.collect()).unwrap()
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        let grid = grid();
+        assert_eq!(grid.row(1), [(1, 0), (1, 1), (1, 2)]);
+        assert_eq!(
+            grid.column(2).copied().collect::<Vec<_>>(),
+            [(0, 2), (1, 2), (2, 2)]
+        );
+        assert_eq!(grid.columns().count(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "column 3 is out of bounds")]
+    fn column_out_of_bounds() {
+        let _ = grid().column(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "row 3 is out of bounds")]
+    fn row_out_of_bounds() {
+        grid().row(3);
+    }
+}